@@ -1,7 +1,7 @@
 use libwebp_sys::*;
 
 use crate::shared::PixelLayout;
-use crate::AnimFrame;
+use crate::{AnimFrame, Metadata, OwnedAnimFrame};
 
 pub struct AnimDecoder<'a> {
     data: &'a [u8],
@@ -70,12 +70,12 @@ impl<'a> AnimDecoder<'a> {
             }
         }
         WebPAnimDecoderReset(dec);
-        //let demuxer:WebPDemuxer=WebPAnimDecoderGetDemuxer(dec);
-        // ... (Do something using 'demuxer'; e.g. get EXIF/XMP/ICC data).
+        let metadata = Metadata::from_demuxer(WebPAnimDecoderGetDemuxer(dec));
         WebPAnimDecoderDelete(dec);
         let mut anim = DecodeAnimImage::from(list);
         anim.loop_count = anim_info.loop_count;
         anim.bg_color = anim_info.bgcolor;
+        anim.metadata = metadata;
         Ok(anim)
     }
 }
@@ -90,6 +90,7 @@ pub struct DecodeAnimImage {
     frames: Vec<DecodeAnimFrame>,
     pub loop_count: u32,
     pub bg_color: u32,
+    metadata: Metadata,
 }
 impl From<Vec<DecodeAnimFrame>> for DecodeAnimImage {
     fn from(frames: Vec<DecodeAnimFrame>) -> Self {
@@ -97,62 +98,104 @@ impl From<Vec<DecodeAnimFrame>> for DecodeAnimImage {
             frames,
             loop_count: 0,
             bg_color: 0,
+            metadata: Metadata::default(),
         }
     }
 }
+impl DecodeAnimFrame {
+    fn as_frame(&self) -> AnimFrame<'_> {
+        AnimFrame::new(
+            &self.img,
+            self.layout,
+            self.width,
+            self.height,
+            self.timestamp,
+            None,
+        )
+    }
+    fn into_frame(self) -> OwnedAnimFrame {
+        OwnedAnimFrame::from_vec(
+            self.img,
+            self.layout,
+            self.width,
+            self.height,
+            self.timestamp,
+        )
+    }
+}
 impl DecodeAnimImage {
     #[inline]
-    pub fn get_frame(&self, index: usize) -> Option<AnimFrame> {
+    pub fn get_frame(&self, index: usize) -> Option<AnimFrame<'_>> {
         let f = self.frames.get(index)?;
-        Some(AnimFrame::new(
-            &f.img,
-            f.layout,
-            f.width,
-            f.height,
-            f.timestamp,
-            None,
-        ))
+        Some(f.as_frame())
     }
     #[inline]
-    pub fn get_frames(&self, index: core::ops::Range<usize>) -> Option<Vec<AnimFrame>> {
+    pub fn get_frames(&self, index: core::ops::Range<usize>) -> Option<Vec<AnimFrame<'_>>> {
         let dec_frames = self.frames.get(index)?;
-        let mut frames = Vec::with_capacity(dec_frames.len());
-        for f in dec_frames {
-            frames.push(AnimFrame::new(
-                &f.img,
-                f.layout,
-                f.width,
-                f.height,
-                f.timestamp,
-                None,
-            ));
-        }
-        Some(frames)
+        Some(dec_frames.iter().map(DecodeAnimFrame::as_frame).collect())
+    }
+    /// Consumes the animation and returns its frames, handing over the decoded pixel buffers without copying them.
+    pub fn into_frames(self) -> Vec<OwnedAnimFrame> {
+        self.frames
+            .into_iter()
+            .map(DecodeAnimFrame::into_frame)
+            .collect()
     }
     pub fn len(&self) -> usize {
         self.frames.len()
     }
+    pub fn is_empty(&self) -> bool {
+        self.frames.is_empty()
+    }
     pub fn has_animation(&self) -> bool {
         self.len() > 1
     }
+    /// Returns the ICC color profile embedded in the animation, if any.
+    pub fn icc_profile(&self) -> Option<&[u8]> {
+        self.metadata.icc_profile.as_deref()
+    }
+    /// Returns the EXIF metadata embedded in the animation, if any.
+    pub fn exif(&self) -> Option<&[u8]> {
+        self.metadata.exif.as_deref()
+    }
+    /// Returns the XMP metadata embedded in the animation, if any.
+    pub fn xmp(&self) -> Option<&[u8]> {
+        self.metadata.xmp.as_deref()
+    }
+    /// Returns all metadata embedded in the animation.
+    pub fn metadata(&self) -> &Metadata {
+        &self.metadata
+    }
     pub fn sort_by_time_stamp(&mut self) {
-        self.frames.sort_by(|a, b| a.timestamp.cmp(&b.timestamp));
+        self.frames.sort_by_key(|f| f.timestamp);
     }
 }
 impl<'a> IntoIterator for &'a DecodeAnimImage {
     type Item = AnimFrame<'a>;
-    type IntoIter = std::vec::IntoIter<Self::Item>;
+    type IntoIter = AnimFrames<'a>;
 
     fn into_iter(self) -> Self::IntoIter {
-        let fs = self.get_frames(0..self.frames.len());
-        if let Some(v) = fs {
-            v.into_iter()
-        } else {
-            vec![].into_iter()
-        }
+        AnimFrames(self.frames.iter())
     }
 }
 
+/// An iterator over the frames of a [DecodeAnimImage] which borrows their pixel data.
+pub struct AnimFrames<'a>(std::slice::Iter<'a, DecodeAnimFrame>);
+
+impl<'a> Iterator for AnimFrames<'a> {
+    type Item = AnimFrame<'a>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.0.next().map(DecodeAnimFrame::as_frame)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.0.size_hint()
+    }
+}
+
+impl ExactSizeIterator for AnimFrames<'_> {}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -186,7 +229,7 @@ mod tests {
         let result = decoder.decode();
         assert!(result.is_ok(), "Decoding should succeed for valid data");
         let anim = result.unwrap();
-        assert!(anim.len() > 0, "Animation should have at least one frame");
+        assert!(!anim.is_empty(), "Animation should have at least one frame");
         let _ = anim.loop_count;
         let _ = anim.bg_color;
     }
@@ -231,6 +274,19 @@ mod tests {
         assert_eq!(count, anim.len());
     }
 
+    #[test]
+    fn test_into_frames_moves_buffers() {
+        let data = minimal_webp_animation();
+        let anim = AnimDecoder::new(&data).decode().unwrap();
+        let ptrs: Vec<_> = anim.frames.iter().map(|f| f.img.as_ptr()).collect();
+        let frames = anim.into_frames();
+        assert_eq!(frames.len(), ptrs.len());
+        for (frame, ptr) in frames.into_iter().zip(ptrs) {
+            let img = frame.into_vec();
+            assert_eq!(img.as_ptr(), ptr);
+        }
+    }
+
     #[test]
     fn test_decode_failure_on_invalid_data() {
         let data = vec![0u8; 10];
@@ -0,0 +1,507 @@
+use std::ops::Range;
+
+use libwebp_sys::{
+    WebPConfig, WebPEncodingError, WebPFeatureFlags, WebPMuxAnimBlend, WebPMuxAnimDispose,
+    WebPMuxAnimParams, WebPMuxError,
+};
+
+use crate::animation_encoder::bgcolor_from_rgba;
+use crate::shared::WebPMemory;
+use crate::{
+    AnimDecoder, BitstreamFeatures, DecodeAnimImage, Encoder, Metadata, Mux, MuxFrameParams,
+};
+
+/// Changes the loop count and background color of an animated WebP file.
+///
+/// The background color is given as RGBA like for [AnimEncoder::set_bgcolor](crate::AnimEncoder::set_bgcolor).
+/// A `loop_count` of 0 means infinite looping.
+///
+/// Only the container is rewritten, the frames are copied unchanged so no quality is lost.
+/// Returns `WEBP_MUX_INVALID_ARGUMENT` if the file isn't animated.
+pub fn set_animation_params(
+    data: &[u8],
+    loop_count: i32,
+    bgcolor: [u8; 4],
+) -> Result<WebPMemory, WebPMuxError> {
+    let mut mux = Mux::from_data(data)?;
+    if mux.features()? & WebPFeatureFlags::ANIMATION_FLAG as u32 == 0 {
+        return Err(WebPMuxError::WEBP_MUX_INVALID_ARGUMENT);
+    }
+    mux.set_animation_params(&WebPMuxAnimParams {
+        bgcolor: bgcolor_from_rgba(bgcolor),
+        loop_count,
+    })?;
+    mux.assemble()
+}
+
+#[derive(Debug)]
+pub enum AnimEditError {
+    WebPMuxError(WebPMuxError),
+    WebPEncodingError(WebPEncodingError),
+    /// A source animation couldn't be decoded to re-encode a frame.
+    DecodeError(String),
+    /// The animations to concatenate have different canvas sizes.
+    CanvasMismatch,
+    /// A frame index is out of range.
+    IndexOutOfRange(usize),
+    /// There are no frames left to encode.
+    NoFrames,
+}
+
+impl From<WebPMuxError> for AnimEditError {
+    fn from(error: WebPMuxError) -> Self {
+        AnimEditError::WebPMuxError(error)
+    }
+}
+
+/// A frame of a source animation, as stored in the file.
+struct SourceFrame {
+    bitstream: WebPMemory,
+    params: MuxFrameParams,
+    width: u32,
+    height: u32,
+    has_alpha: bool,
+}
+
+/// An animation the frames of an [AnimEditor] come from.
+struct Source {
+    /// The original file, decoded only if a frame has to be re-encoded.
+    data: Vec<u8>,
+    frames: Vec<SourceFrame>,
+}
+
+#[derive(Copy, Clone, Debug)]
+struct EditFrame {
+    source: usize,
+    index: usize,
+    duration: u32,
+}
+
+/// What the canvas looks like before a frame is drawn.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum CanvasState {
+    /// Fully transparent, like before the first frame.
+    Cleared,
+    /// Like in the source animation after the frame at `index` was drawn and disposed.
+    After {
+        source: usize,
+        index: usize,
+    },
+    Unknown,
+}
+
+/// Edits the frames of an animation without decoding and re-encoding all of them.
+///
+/// The compressed data of a frame is copied unchanged when the canvas it is drawn onto looks the same as in
+/// its source animation, or when it doesn't depend on the canvas at all.
+/// Other frames are replaced by the fully rendered canvas of their source animation, encoded with
+/// the configuration set by [set_config](AnimEditor::set_config), which is lossless by default.
+///
+/// The loop count, background color and metadata are taken from the first animation.
+pub struct AnimEditor {
+    sources: Vec<Source>,
+    frames: Vec<EditFrame>,
+    width: u32,
+    height: u32,
+    params: WebPMuxAnimParams,
+    metadata: Metadata,
+    config: WebPConfig,
+}
+
+impl AnimEditor {
+    /// Reads the frames of an animated WebP file.
+    pub fn new(data: &[u8]) -> Result<Self, AnimEditError> {
+        let mux = Mux::from_data(data)?;
+        if mux.features()? & WebPFeatureFlags::ANIMATION_FLAG as u32 == 0 {
+            return Err(AnimEditError::WebPMuxError(
+                WebPMuxError::WEBP_MUX_INVALID_ARGUMENT,
+            ));
+        }
+        let (width, height) = mux.canvas_size()?;
+        let params = mux.animation_params()?;
+        let mut frames = Vec::new();
+        for index in 0..mux.num_frames()? {
+            let frame = mux.get_frame(index)?;
+            let features = BitstreamFeatures::new(&frame.bitstream)
+                .ok_or(AnimEditError::WebPMuxError(WebPMuxError::WEBP_MUX_BAD_DATA))?;
+            frames.push(SourceFrame {
+                width: features.width(),
+                height: features.height(),
+                has_alpha: features.has_alpha(),
+                bitstream: frame.bitstream,
+                params: frame.params,
+            });
+        }
+        let mut config = WebPConfig::new().unwrap();
+        config.lossless = 1;
+        config.exact = 1;
+        Ok(Self {
+            frames: (0..frames.len())
+                .map(|index| EditFrame {
+                    source: 0,
+                    index,
+                    duration: frames[index].params.duration,
+                })
+                .collect(),
+            sources: vec![Source {
+                data: data.to_vec(),
+                frames,
+            }],
+            width,
+            height,
+            params,
+            metadata: Metadata::new(data).unwrap_or_default(),
+            config,
+        })
+    }
+
+    /// Returns the number of frames.
+    pub fn len(&self) -> usize {
+        self.frames.len()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.frames.is_empty()
+    }
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    /// Returns the duration of the frame at the given index in milliseconds.
+    pub fn duration(&self, index: usize) -> Option<u32> {
+        self.frames.get(index).map(|frame| frame.duration)
+    }
+    /// Sets the duration of the frame at the given index in milliseconds.
+    pub fn set_duration(&mut self, index: usize, duration: u32) -> Result<(), AnimEditError> {
+        let frame = self
+            .frames
+            .get_mut(index)
+            .ok_or(AnimEditError::IndexOutOfRange(index))?;
+        frame.duration = duration;
+        Ok(())
+    }
+
+    /// Removes the frame at the given index.
+    pub fn remove_frame(&mut self, index: usize) -> Result<(), AnimEditError> {
+        if index >= self.frames.len() {
+            return Err(AnimEditError::IndexOutOfRange(index));
+        }
+        self.frames.remove(index);
+        Ok(())
+    }
+
+    /// Keeps only the frames which start within the given time range in milliseconds.
+    pub fn trim(&mut self, range: Range<u32>) {
+        self.retain_by_time(|start| range.contains(&start));
+    }
+    /// Removes the frames which start within the given time range in milliseconds.
+    pub fn cut(&mut self, range: Range<u32>) {
+        self.retain_by_time(|start| !range.contains(&start));
+    }
+    fn retain_by_time(&mut self, mut keep: impl FnMut(u32) -> bool) {
+        let mut start = 0u32;
+        self.frames.retain(|frame| {
+            let frame_start = start;
+            start = start.saturating_add(frame.duration);
+            keep(frame_start)
+        });
+    }
+
+    /// Rearranges the frames: the new animation shows the frames at the given indices, in this order.
+    /// Frames can be left out or repeated.
+    pub fn reorder(&mut self, order: &[usize]) -> Result<(), AnimEditError> {
+        let frames = order
+            .iter()
+            .map(|&index| {
+                self.frames
+                    .get(index)
+                    .copied()
+                    .ok_or(AnimEditError::IndexOutOfRange(index))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        self.frames = frames;
+        Ok(())
+    }
+
+    /// Appends the frames of another animation with the same canvas size.
+    pub fn append(&mut self, other: AnimEditor) -> Result<(), AnimEditError> {
+        if (self.width, self.height) != (other.width, other.height) {
+            return Err(AnimEditError::CanvasMismatch);
+        }
+        let offset = self.sources.len();
+        self.sources.extend(other.sources);
+        self.frames
+            .extend(other.frames.into_iter().map(|frame| EditFrame {
+                source: frame.source + offset,
+                ..frame
+            }));
+        Ok(())
+    }
+
+    /// Sets the configuration used for frames which have to be re-encoded.
+    pub fn set_config(&mut self, config: &WebPConfig) {
+        self.config = *config;
+    }
+
+    /// Assembles the edited animation.
+    pub fn encode(&self) -> Result<WebPMemory, AnimEditError> {
+        if self.frames.is_empty() {
+            return Err(AnimEditError::NoFrames);
+        }
+        let mut mux = Mux::new();
+        mux.set_canvas_size(self.width, self.height)?;
+        mux.set_animation_params(&self.params)?;
+
+        let mut decoded: Vec<Option<DecodeAnimImage>> = self.sources.iter().map(|_| None).collect();
+        let mut state = CanvasState::Cleared;
+        for frame in &self.frames {
+            let original = &self.sources[frame.source].frames[frame.index];
+            let required = match frame.index {
+                0 => CanvasState::Cleared,
+                index => self.state_after(frame.source, index - 1),
+            };
+            if state == required || self.is_self_contained(original) {
+                let params = MuxFrameParams {
+                    duration: frame.duration,
+                    ..original.params
+                };
+                mux.push_frame(&original.bitstream, &params)?;
+                state = self.state_after(frame.source, frame.index);
+                continue;
+            }
+
+            // Replace the frame by the canvas as rendered in its source animation.
+            let anim = match &mut decoded[frame.source] {
+                Some(anim) => anim,
+                slot => slot.insert(
+                    AnimDecoder::new(&self.sources[frame.source].data)
+                        .decode()
+                        .map_err(AnimEditError::DecodeError)?,
+                ),
+            };
+            let canvas = anim
+                .get_frame(frame.index)
+                .ok_or_else(|| AnimEditError::DecodeError(String::from("missing frame")))?;
+            let bitstream = Encoder::from_rgba(canvas.get_image(), self.width, self.height)
+                .encode_advanced(&self.config)
+                .map_err(AnimEditError::WebPEncodingError)?;
+            let covers_canvas = self.covers_canvas(original);
+            let dispose_method = if covers_canvas {
+                original.params.dispose_method
+            } else {
+                WebPMuxAnimDispose::WEBP_MUX_DISPOSE_NONE
+            };
+            let params = MuxFrameParams {
+                x_offset: 0,
+                y_offset: 0,
+                duration: frame.duration,
+                dispose_method,
+                blend_method: WebPMuxAnimBlend::WEBP_MUX_NO_BLEND,
+            };
+            mux.push_frame(&bitstream, &params)?;
+            state = if covers_canvas
+                || original.params.dispose_method == WebPMuxAnimDispose::WEBP_MUX_DISPOSE_NONE
+            {
+                self.state_after(frame.source, frame.index)
+            } else {
+                CanvasState::Unknown
+            };
+        }
+
+        mux.set_metadata_chunks(
+            self.metadata.icc_profile.as_deref(),
+            self.metadata.exif.as_deref(),
+            self.metadata.xmp.as_deref(),
+        )?;
+        Ok(mux.assemble()?)
+    }
+
+    fn state_after(&self, source: usize, index: usize) -> CanvasState {
+        let frame = &self.sources[source].frames[index];
+        if self.covers_canvas(frame)
+            && frame.params.dispose_method == WebPMuxAnimDispose::WEBP_MUX_DISPOSE_BACKGROUND
+        {
+            CanvasState::Cleared
+        } else {
+            CanvasState::After { source, index }
+        }
+    }
+
+    fn covers_canvas(&self, frame: &SourceFrame) -> bool {
+        frame.params.x_offset == 0
+            && frame.params.y_offset == 0
+            && frame.width == self.width
+            && frame.height == self.height
+    }
+
+    /// Returns true if the frame looks the same whatever is on the canvas.
+    fn is_self_contained(&self, frame: &SourceFrame) -> bool {
+        self.covers_canvas(frame)
+            && (!frame.has_alpha
+                || frame.params.blend_method == WebPMuxAnimBlend::WEBP_MUX_NO_BLEND)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{AnimEncoder, AnimFrame};
+
+    fn animation() -> WebPMemory {
+        let config = WebPConfig::new().unwrap();
+        let mut encoder = AnimEncoder::new(4, 4, &config);
+        encoder.set_loop_count(3);
+        for (i, value) in [0u8, 255].into_iter().enumerate() {
+            let frame = vec![value; 4 * 4 * 4];
+            encoder.add_frame(AnimFrame::from_rgba(&frame, 4, 4, i as i32 * 100).into_owned());
+        }
+        encoder.encode()
+    }
+
+    #[test]
+    fn test_set_animation_params() {
+        let data = animation();
+        let edited = set_animation_params(&data, 0, [0x10, 0x20, 0x40, 0x80]).unwrap();
+        let anim = AnimDecoder::new(&edited).decode().unwrap();
+        assert_eq!(anim.loop_count, 0);
+        assert_eq!(anim.bg_color, 0x80402010);
+        assert_eq!(anim.len(), 2);
+
+        // The frames are unchanged.
+        let original = Mux::from_data(&data).unwrap();
+        let edited = Mux::from_data(&edited).unwrap();
+        for i in 0..2 {
+            assert_eq!(
+                *original.get_frame(i).unwrap().bitstream,
+                *edited.get_frame(i).unwrap().bitstream
+            );
+        }
+    }
+
+    #[test]
+    fn test_set_animation_params_errors() {
+        let still = Encoder::from_rgb(&[0u8; 4 * 4 * 3], 4, 4).encode_lossless();
+        assert_eq!(
+            set_animation_params(&still, 0, [0; 4]).unwrap_err(),
+            WebPMuxError::WEBP_MUX_INVALID_ARGUMENT
+        );
+        assert_eq!(
+            set_animation_params(&animation(), -1, [0; 4]).unwrap_err(),
+            WebPMuxError::WEBP_MUX_INVALID_ARGUMENT
+        );
+        assert!(set_animation_params(b"not a webp", 0, [0; 4]).is_err());
+    }
+
+    /// An animation whose later frames only change parts of the canvas,
+    /// so the encoder stores them as sub-frames which are blended onto the previous ones.
+    fn partial_animation() -> Vec<u8> {
+        let mut config = WebPConfig::new().unwrap();
+        config.lossless = 1;
+        let mut encoder = AnimEncoder::new(16, 16, &config);
+        let mut canvas: Vec<u8> = (0..16 * 16 * 4).map(|i| (i % 251) as u8).collect();
+        for i in 0..4 {
+            for y in 4 * i..4 * i + 4 {
+                let row = (y * 16 + 4 * i) * 4;
+                canvas[row..row + 16].fill(200 - 40 * i as u8);
+            }
+            encoder.add_frame(AnimFrame::from_rgba(&canvas, 16, 16, i as i32 * 100).into_owned());
+        }
+        encoder.encode().to_vec()
+    }
+
+    fn decoded_frames(data: &[u8]) -> Vec<Vec<u8>> {
+        let anim = AnimDecoder::new(data).decode().unwrap();
+        (&anim)
+            .into_iter()
+            .map(|f| f.get_image().to_vec())
+            .collect()
+    }
+
+    fn frame_bitstreams(data: &[u8]) -> Vec<Vec<u8>> {
+        let mux = Mux::from_data(data).unwrap();
+        (0..mux.num_frames().unwrap())
+            .map(|i| mux.get_frame(i).unwrap().bitstream.to_vec())
+            .collect()
+    }
+
+    #[test]
+    fn test_edit_copies_frames() {
+        let data = partial_animation();
+        let mut editor = AnimEditor::new(&data).unwrap();
+        assert_eq!(editor.len(), 4);
+        assert_eq!((editor.width(), editor.height()), (16, 16));
+        assert_eq!(editor.duration(0), Some(100));
+        editor.set_duration(0, 500).unwrap();
+        editor.trim(0..700);
+        assert_eq!(editor.len(), 3);
+
+        let edited = editor.encode().unwrap();
+        assert_eq!(frame_bitstreams(&edited), frame_bitstreams(&data)[..3]);
+        assert_eq!(
+            Mux::from_data(&edited)
+                .unwrap()
+                .get_frame(0)
+                .unwrap()
+                .params
+                .duration,
+            500
+        );
+        assert_eq!(decoded_frames(&edited), decoded_frames(&data)[..3]);
+    }
+
+    #[test]
+    fn test_edit_reencodes_frames() {
+        let data = partial_animation();
+        let mux = Mux::from_data(&data).unwrap();
+        assert_ne!(mux.get_frame(2).unwrap().params.x_offset, 0);
+        let original = decoded_frames(&data);
+        let pick = |order: &[usize]| -> Vec<Vec<u8>> {
+            order.iter().map(|&i| original[i].clone()).collect()
+        };
+
+        let mut editor = AnimEditor::new(&data).unwrap();
+        editor.remove_frame(1).unwrap();
+        let edited = editor.encode().unwrap();
+        assert_eq!(decoded_frames(&edited), pick(&[0, 2, 3]));
+
+        let mut editor = AnimEditor::new(&data).unwrap();
+        editor.reorder(&[3, 2, 1, 0, 0]).unwrap();
+        let edited = editor.encode().unwrap();
+        assert_eq!(decoded_frames(&edited), pick(&[3, 2, 1, 0, 0]));
+
+        let mut editor = AnimEditor::new(&data).unwrap();
+        editor.cut(100..300);
+        editor.append(AnimEditor::new(&data).unwrap()).unwrap();
+        let edited = editor.encode().unwrap();
+        assert_eq!(decoded_frames(&edited), pick(&[0, 3, 0, 1, 2, 3]));
+        // The second animation starts with a keyframe, so it is copied unchanged.
+        let bitstreams = frame_bitstreams(&data);
+        assert_eq!(frame_bitstreams(&edited)[2..], bitstreams[..]);
+    }
+
+    #[test]
+    fn test_edit_errors() {
+        let data = partial_animation();
+        let mut editor = AnimEditor::new(&data).unwrap();
+        assert!(matches!(
+            editor.remove_frame(4),
+            Err(AnimEditError::IndexOutOfRange(4))
+        ));
+        assert!(matches!(
+            editor.reorder(&[0, 7]),
+            Err(AnimEditError::IndexOutOfRange(7))
+        ));
+        assert!(matches!(
+            editor.append(AnimEditor::new(&animation()).unwrap()),
+            Err(AnimEditError::CanvasMismatch)
+        ));
+        editor.trim(0..0);
+        assert!(editor.is_empty());
+        assert!(matches!(editor.encode(), Err(AnimEditError::NoFrames)));
+
+        let still = Encoder::from_rgb(&[0u8; 4 * 4 * 3], 4, 4).encode_lossless();
+        assert!(AnimEditor::new(&still).is_err());
+    }
+}
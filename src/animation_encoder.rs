@@ -1,16 +1,25 @@
+use std::borrow::Cow;
+
 #[cfg(feature = "img")]
 use image::{DynamicImage, ImageBuffer};
 use libwebp_sys::*;
 
-use crate::{shared::*, Encoder};
+#[cfg(feature = "img")]
+use crate::convert::from_dynamic;
+use crate::{shared::*, Encoder, Mux};
 
+/// A single frame of an animation.
+/// The pixel data is either borrowed or owned, see [OwnedAnimFrame].
 pub struct AnimFrame<'a> {
-    image: &'a [u8],
+    image: Cow<'a, [u8]>,
     layout: PixelLayout,
     width: u32,
     height: u32,
+    x_offset: u32,
+    y_offset: u32,
+    stride: usize,
     timestamp: i32,
-    config: Option<&'a WebPConfig>,
+    config: Option<WebPConfig>,
 }
 impl<'a> AnimFrame<'a> {
     pub fn new(
@@ -22,33 +31,32 @@ impl<'a> AnimFrame<'a> {
         config: Option<&'a WebPConfig>,
     ) -> Self {
         Self {
-            image,
+            image: Cow::Borrowed(image),
             layout,
             width,
             height,
+            x_offset: 0,
+            y_offset: 0,
+            stride: packed_stride(layout, width),
             timestamp,
-            config,
+            config: config.copied(),
         }
     }
+    /// Creates a new frame from the given image, converting it like `Encoder::from_image` does.
     #[cfg(feature = "img")]
     pub fn from_image(image: &'a DynamicImage, timestamp: i32) -> Result<Self, &'a str> {
-        match image {
-            DynamicImage::ImageLuma8(_) => Err("Unimplemented"),
-            DynamicImage::ImageLumaA8(_) => Err("Unimplemented"),
-            DynamicImage::ImageRgb8(image) => Ok(Self::from_rgb(
-                image.as_ref(),
-                image.width(),
-                image.height(),
-                timestamp,
-            )),
-            DynamicImage::ImageRgba8(image) => Ok(Self::from_rgba(
-                image.as_ref(),
-                image.width(),
-                image.height(),
-                timestamp,
-            )),
-            _ => Err("Unimplemented"),
-        }
+        let (image_data, layout) = from_dynamic(image).ok_or("Unimplemented")?;
+        Ok(Self {
+            image: image_data,
+            layout,
+            width: image.width(),
+            height: image.height(),
+            x_offset: 0,
+            y_offset: 0,
+            stride: packed_stride(layout, image.width()),
+            timestamp,
+            config: None,
+        })
     }
     /// Creates a new encoder from the given image data in the RGB pixel layout.
     pub fn from_rgb(image: &'a [u8], width: u32, height: u32, timestamp: i32) -> Self {
@@ -59,7 +67,7 @@ impl<'a> AnimFrame<'a> {
         Self::new(image, PixelLayout::Rgba, width, height, timestamp, None)
     }
     pub fn get_image(&self) -> &[u8] {
-        self.image
+        &self.image
     }
     pub fn get_layout(&self) -> PixelLayout {
         self.layout
@@ -73,24 +81,145 @@ impl<'a> AnimFrame<'a> {
     pub fn height(&self) -> u32 {
         self.height
     }
+    /// Places the frame at the given offset within the canvas of the animation.
+    ///
+    /// A frame which is smaller than the canvas only replaces the pixels of the area it covers,
+    /// the rest of the canvas keeps the contents of the previous frame.
+    /// The pixels are copied as they are and not alpha-blended: transparent pixels of the frame
+    /// make that part of the canvas transparent instead of showing the previous frame through.
+    ///
+    /// The frame is drawn onto a copy of the full canvas, which is then encoded as a whole.
+    /// libwebp usually stores only the changed area, but small frames are neither faster to encode
+    /// nor use less memory than frames covering the whole canvas.
+    pub fn with_offset(mut self, x_offset: u32, y_offset: u32) -> Self {
+        self.x_offset = x_offset;
+        self.y_offset = y_offset;
+        self
+    }
+    pub fn x_offset(&self) -> u32 {
+        self.x_offset
+    }
+    pub fn y_offset(&self) -> u32 {
+        self.y_offset
+    }
+    /// Sets the distance between the starts of two rows of the image data in bytes,
+    /// for padded rows or a sub-rectangle of a larger image.
+    /// It is checked when the frame is encoded and must be at least `width * bytes_per_pixel`.
+    pub fn with_stride(mut self, stride: usize) -> Self {
+        self.stride = stride;
+        self
+    }
+    pub fn stride(&self) -> usize {
+        self.stride
+    }
+    /// Returns the image data without the padding between rows, borrowing it if there is none.
+    fn packed_image(&self) -> Cow<'_, [u8]> {
+        let row_len = packed_stride(self.layout, self.width);
+        if self.stride == row_len {
+            return Cow::Borrowed(&self.image);
+        }
+        let mut packed = Vec::with_capacity(row_len * self.height as usize);
+        for y in 0..self.height as usize {
+            let start = y * self.stride;
+            packed.extend_from_slice(&self.image[start..start + row_len]);
+        }
+        Cow::Owned(packed)
+    }
+    /// Converts this frame into one that owns its pixel data, copying it only if it is borrowed.
+    pub fn into_owned(self) -> OwnedAnimFrame {
+        AnimFrame {
+            image: Cow::Owned(self.image.into_owned()),
+            layout: self.layout,
+            width: self.width,
+            height: self.height,
+            x_offset: self.x_offset,
+            y_offset: self.y_offset,
+            stride: self.stride,
+            timestamp: self.timestamp,
+            config: self.config,
+        }
+    }
+    /// Returns the pixel data of this frame without padding between rows,
+    /// copying it only if it is borrowed or padded.
+    pub fn into_vec(self) -> Vec<u8> {
+        if self.stride != packed_stride(self.layout, self.width) {
+            return self.packed_image().into_owned();
+        }
+        self.image.into_owned()
+    }
+    /// Creates a DynamicImage from this frame, copying the pixel data only if it is borrowed or padded.
+    #[cfg(feature = "img")]
+    pub fn into_image(self) -> DynamicImage {
+        let (width, height) = (self.width, self.height);
+        if self.layout.is_alpha() {
+            let image = ImageBuffer::from_raw(width, height, self.into_vec())
+                .expect("ImageBuffer couldn't be created");
+            DynamicImage::ImageRgba8(image)
+        } else {
+            let image = ImageBuffer::from_raw(width, height, self.into_vec())
+                .expect("ImageBuffer couldn't be created");
+            DynamicImage::ImageRgb8(image)
+        }
+    }
+}
+
+fn packed_stride(layout: PixelLayout, width: u32) -> usize {
+    width as usize * usize::from(layout.bytes_per_pixel())
 }
+
+/// An animation frame that owns its pixel data.
+///
+/// Since an `AnimEncoder<'a>` accepts any frame that lives at least as long as `'a`,
+/// owned frames can be added to it without keeping the source buffers around.
+pub type OwnedAnimFrame = AnimFrame<'static>;
+
+impl OwnedAnimFrame {
+    /// Creates a new frame which takes ownership of the given image data.
+    /// The image data must be in the pixel layout of the layout parameter.
+    pub fn from_vec(
+        image: Vec<u8>,
+        layout: PixelLayout,
+        width: u32,
+        height: u32,
+        timestamp: i32,
+    ) -> Self {
+        Self {
+            image: Cow::Owned(image),
+            layout,
+            width,
+            height,
+            x_offset: 0,
+            y_offset: 0,
+            stride: packed_stride(layout, width),
+            timestamp,
+            config: None,
+        }
+    }
+}
+
 impl<'a> From<&'a AnimFrame<'a>> for Encoder<'a> {
     fn from(f: &'a AnimFrame) -> Self {
-        Encoder::new(f.get_image(), f.layout, f.width, f.height)
+        Encoder::with_stride(f.get_image(), f.layout, f.width, f.height, f.stride)
     }
 }
 #[cfg(feature = "img")]
 impl From<&AnimFrame<'_>> for DynamicImage {
     fn from(value: &AnimFrame<'_>) -> DynamicImage {
         if value.layout.is_alpha() {
-            let image =
-                ImageBuffer::from_raw(value.width(), value.height(), value.get_image().to_owned())
-                    .expect("ImageBuffer couldn't be created");
+            let image = ImageBuffer::from_raw(
+                value.width(),
+                value.height(),
+                value.packed_image().into_owned(),
+            )
+            .expect("ImageBuffer couldn't be created");
             DynamicImage::ImageRgba8(image)
         } else {
-            let image =
-                ImageBuffer::from_raw(value.width(), value.height(), value.get_image().to_owned())
-                    .expect("ImageBuffer couldn't be created");
+            let image = ImageBuffer::from_raw(
+                value.width(),
+                value.height(),
+                value.packed_image().into_owned(),
+            )
+            .expect("ImageBuffer couldn't be created");
             DynamicImage::ImageRgb8(image)
         }
     }
@@ -101,6 +230,10 @@ pub struct AnimEncoder<'a> {
     height: u32,
     config: &'a WebPConfig,
     muxparams: WebPMuxAnimParams,
+    options: AnimEncoderOptions,
+    icc_profile: Option<&'a [u8]>,
+    exif: Option<&'a [u8]>,
+    xmp: Option<&'a [u8]>,
 }
 impl<'a> AnimEncoder<'a> {
     pub fn new(width: u32, height: u32, config: &'a WebPConfig) -> Self {
@@ -113,18 +246,34 @@ impl<'a> AnimEncoder<'a> {
                 bgcolor: 0,
                 loop_count: 0,
             },
+            options: AnimEncoderOptions::default(),
+            icc_profile: None,
+            exif: None,
+            xmp: None,
         }
     }
     pub fn set_bgcolor(&mut self, rgba: [u8; 4]) {
-        let bgcolor = (u32::from(rgba[3]) << 24)
-            + (u32::from(rgba[2]) << 16)
-            + (u32::from(rgba[1]) << 8)
-            + (u32::from(rgba[0]));
-        self.muxparams.bgcolor = bgcolor;
+        self.muxparams.bgcolor = bgcolor_from_rgba(rgba);
     }
     pub fn set_loop_count(&mut self, loop_count: i32) {
         self.muxparams.loop_count = loop_count;
     }
+    /// Sets the options used to encode the animation.
+    pub fn set_options(&mut self, options: AnimEncoderOptions) {
+        self.options = options;
+    }
+    /// Embeds the given ICC color profile in the encoded animation.
+    pub fn set_icc_profile(&mut self, icc_profile: &'a [u8]) {
+        self.icc_profile = Some(icc_profile);
+    }
+    /// Embeds the given EXIF metadata in the encoded animation.
+    pub fn set_exif(&mut self, exif: &'a [u8]) {
+        self.exif = Some(exif);
+    }
+    /// Embeds the given XMP metadata in the encoded animation.
+    pub fn set_xmp(&mut self, xmp: &'a [u8]) {
+        self.xmp = Some(xmp);
+    }
     pub fn add_frame(&mut self, frame: AnimFrame<'a>) {
         self.frames.push(frame);
     }
@@ -132,7 +281,81 @@ impl<'a> AnimEncoder<'a> {
         self.try_encode().unwrap()
     }
     pub fn try_encode(&self) -> Result<WebPMemory, AnimEncodeError> {
-        unsafe { anim_encode(self) }
+        let mut encoder =
+            AnimStreamEncoder::with_options(self.width, self.height, self.config, &self.options)?;
+        encoder.muxparams = self.muxparams;
+        encoder.icc_profile = self.icc_profile.map(<[u8]>::to_vec);
+        encoder.exif = self.exif.map(<[u8]>::to_vec);
+        encoder.xmp = self.xmp.map(<[u8]>::to_vec);
+        for frame in self.frames.iter() {
+            encoder.push_frame(frame)?;
+        }
+        encoder.finish()
+    }
+}
+
+pub(crate) fn bgcolor_from_rgba(rgba: [u8; 4]) -> u32 {
+    (u32::from(rgba[3]) << 24)
+        + (u32::from(rgba[2]) << 16)
+        + (u32::from(rgba[1]) << 8)
+        + (u32::from(rgba[0]))
+}
+
+/// Options for encoding an animation, mirroring libwebp's `WebPAnimEncoderOptions`.
+/// The defaults are the ones of libwebp.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct AnimEncoderOptions {
+    /// Minimizes the output size at the cost of encoding speed. Implicitly disables key-frame insertion.
+    pub minimize_size: bool,
+    /// Minimum distance between consecutive key-frames in the output.
+    pub kmin: i32,
+    /// Maximum distance between consecutive key-frames in the output.
+    /// If `kmax <= 0`, key-frame insertion is disabled. If `kmax == 1`, all frames are key-frames.
+    /// Otherwise libwebp adjusts `kmin` so that `kmax > kmin >= kmax / 2 + 1` holds.
+    pub kmax: i32,
+    /// Allows choosing lossy or lossless compression for each frame individually.
+    pub allow_mixed: bool,
+    /// Prints info and warning messages to stderr.
+    pub verbose: bool,
+}
+
+impl AnimEncoderOptions {
+    /// Inserts a key-frame at least every `kmax` and at most every `kmin` frames.
+    pub fn keyframe_interval(mut self, kmin: i32, kmax: i32) -> Self {
+        self.kmin = kmin;
+        self.kmax = kmax;
+        self
+    }
+
+    fn to_raw(self) -> WebPAnimEncoderOptions {
+        let mut options = raw_anim_encoder_options();
+        options.minimize_size = self.minimize_size as i32;
+        options.kmin = self.kmin;
+        options.kmax = self.kmax;
+        options.allow_mixed = self.allow_mixed as i32;
+        options.verbose = self.verbose as i32;
+        options
+    }
+}
+
+impl Default for AnimEncoderOptions {
+    fn default() -> Self {
+        let options = raw_anim_encoder_options();
+        Self {
+            minimize_size: options.minimize_size != 0,
+            kmin: options.kmin,
+            kmax: options.kmax,
+            allow_mixed: options.allow_mixed != 0,
+            verbose: options.verbose != 0,
+        }
+    }
+}
+
+fn raw_anim_encoder_options() -> WebPAnimEncoderOptions {
+    unsafe {
+        let mut options = std::mem::MaybeUninit::<WebPAnimEncoderOptions>::uninit();
+        WebPAnimEncoderOptionsInitInternal(options.as_mut_ptr(), WebPGetMuxABIVersion());
+        options.assume_init()
     }
 }
 
@@ -141,43 +364,196 @@ pub enum AnimEncodeError {
     WebPEncodingError(WebPEncodingError),
     WebPMuxError(WebPMuxError),
     WebPAnimEncoderGetError(String),
+    /// The frame's buffer or dimensions don't fit the frame or the canvas.
+    InvalidFrame(String),
 }
-unsafe fn anim_encode(all_frame: &AnimEncoder) -> Result<WebPMemory, AnimEncodeError> {
-    let width = all_frame.width;
-    let height = all_frame.height;
-    let mut uninit = std::mem::MaybeUninit::<WebPAnimEncoderOptions>::uninit();
-
-    let mux_abi_version = WebPGetMuxABIVersion();
-    WebPAnimEncoderOptionsInitInternal(uninit.as_mut_ptr(), mux_abi_version);
-    let encoder = WebPAnimEncoderNewInternal(
-        width as i32,
-        height as i32,
-        uninit.as_ptr(),
-        mux_abi_version,
-    );
-    let mut frame_pictures = vec![];
-    for frame in all_frame.frames.iter() {
-        let mut pic = crate::new_picture(frame.image, frame.layout, width, height);
-        let config = frame.config.unwrap_or(all_frame.config);
-        let ok = WebPAnimEncoderAdd(
+
+/// An animation encoder which encodes every frame as soon as it is pushed.
+///
+/// Unlike [AnimEncoder], it does not keep the frames around until the animation is assembled:
+/// the pixels of a frame are no longer needed once [push_frame](AnimStreamEncoder::push_frame) returns.
+/// This makes it possible to encode animations of arbitrary length with bounded memory usage.
+/// The encoder keeps one RGBA buffer of the size of the canvas, which every frame is drawn onto before it is encoded.
+pub struct AnimStreamEncoder {
+    encoder: *mut WebPAnimEncoder,
+    width: u32,
+    height: u32,
+    config: WebPConfig,
+    muxparams: WebPMuxAnimParams,
+    /// The RGBA contents of the canvas after the last pushed frame.
+    /// libwebp only accepts frames covering the whole canvas, so frames placed at an offset are composed onto it.
+    canvas: Vec<u8>,
+    icc_profile: Option<Vec<u8>>,
+    exif: Option<Vec<u8>>,
+    xmp: Option<Vec<u8>>,
+}
+
+impl AnimStreamEncoder {
+    /// Creates a new streaming encoder for an animation with the given canvas size.
+    /// The config is used for every frame which doesn't carry its own configuration.
+    pub fn new(width: u32, height: u32, config: &WebPConfig) -> Result<Self, AnimEncodeError> {
+        Self::with_options(width, height, config, &AnimEncoderOptions::default())
+    }
+    /// Creates a new streaming encoder like [new](AnimStreamEncoder::new), using the given encoder options.
+    pub fn with_options(
+        width: u32,
+        height: u32,
+        config: &WebPConfig,
+        options: &AnimEncoderOptions,
+    ) -> Result<Self, AnimEncodeError> {
+        let options = options.to_raw();
+        let encoder = unsafe {
+            WebPAnimEncoderNewInternal(
+                width as i32,
+                height as i32,
+                &options,
+                WebPGetMuxABIVersion(),
+            )
+        };
+        if encoder.is_null() {
+            return Err(AnimEncodeError::WebPAnimEncoderGetError(String::from(
+                "Could not create the animation encoder",
+            )));
+        }
+        Ok(Self {
             encoder,
-            &mut *pic as *mut _,
-            frame.timestamp as std::os::raw::c_int,
-            config,
-        );
+            width,
+            height,
+            config: *config,
+            muxparams: WebPMuxAnimParams {
+                bgcolor: 0,
+                loop_count: 0,
+            },
+            canvas: vec![0; width as usize * height as usize * 4],
+            icc_profile: None,
+            exif: None,
+            xmp: None,
+        })
+    }
+    pub fn set_bgcolor(&mut self, rgba: [u8; 4]) {
+        self.muxparams.bgcolor = bgcolor_from_rgba(rgba);
+    }
+    pub fn set_loop_count(&mut self, loop_count: i32) {
+        self.muxparams.loop_count = loop_count;
+    }
+    /// Embeds the given ICC color profile in the encoded animation.
+    pub fn set_icc_profile(&mut self, icc_profile: &[u8]) {
+        self.icc_profile = Some(icc_profile.to_vec());
+    }
+    /// Embeds the given EXIF metadata in the encoded animation.
+    pub fn set_exif(&mut self, exif: &[u8]) {
+        self.exif = Some(exif.to_vec());
+    }
+    /// Embeds the given XMP metadata in the encoded animation.
+    pub fn set_xmp(&mut self, xmp: &[u8]) {
+        self.xmp = Some(xmp.to_vec());
+    }
+    /// Encodes the given frame and adds it to the animation.
+    /// The frame's pixel data is not referenced after this call and can be dropped or reused right away.
+    ///
+    /// Returns [AnimEncodeError::InvalidFrame] if the frame's buffer is too small for its dimensions,
+    /// or if the frame doesn't fit within the canvas at its offset.
+    pub fn push_frame(&mut self, frame: &AnimFrame<'_>) -> Result<(), AnimEncodeError> {
+        self.check_frame(frame)?;
+        self.compose(frame);
+        unsafe {
+            let mut pic = crate::new_picture(
+                &self.canvas,
+                PixelLayout::Rgba,
+                self.width,
+                self.height,
+                self.width as usize * 4,
+            );
+            let config = frame.config.as_ref().unwrap_or(&self.config);
+            let ok = WebPAnimEncoderAdd(
+                self.encoder,
+                &mut *pic as *mut _,
+                frame.timestamp as std::os::raw::c_int,
+                config,
+            );
+            if ok == 0 {
+                //ok == false
+                return Err(AnimEncodeError::WebPEncodingError(pic.error_code));
+            }
+        }
+        Ok(())
+    }
+    fn check_frame(&self, frame: &AnimFrame<'_>) -> Result<(), AnimEncodeError> {
+        if frame.width == 0 || frame.height == 0 {
+            return Err(AnimEncodeError::InvalidFrame(format!(
+                "Frame dimensions must be non-zero, got {}x{}.",
+                frame.width, frame.height
+            )));
+        }
+        let row_len = packed_stride(frame.layout, frame.width);
+        if frame.stride < row_len {
+            return Err(AnimEncodeError::InvalidFrame(format!(
+                "Stride too small. Expected at least {} bytes for a row of {} pixels with {:?} layout, got {}.",
+                row_len, frame.width, frame.layout, frame.stride
+            )));
+        }
+        // Same reasoning as in `CheckedEncoder::new`: saturating arithmetic can't be used to bypass the check.
+        let expected_len = min_buffer_len(frame.layout, frame.width, frame.height, frame.stride);
+        if frame.image.len() < expected_len {
+            return Err(AnimEncodeError::InvalidFrame(format!(
+                "Frame buffer too small. Expected at least {} bytes for a {}x{} frame with {:?} layout and a stride of {}, got {}.",
+                expected_len, frame.width, frame.height, frame.layout, frame.stride, frame.image.len()
+            )));
+        }
+        let right = frame.x_offset.checked_add(frame.width);
+        let bottom = frame.y_offset.checked_add(frame.height);
+        let fits = |end: Option<u32>, max: u32| matches!(end, Some(end) if end <= max);
+        if !fits(right, self.width) || !fits(bottom, self.height) {
+            return Err(AnimEncodeError::InvalidFrame(format!(
+                "A {}x{} frame at offset ({}, {}) doesn't fit in the {}x{} canvas.",
+                frame.width, frame.height, frame.x_offset, frame.y_offset, self.width, self.height
+            )));
+        }
+        Ok(())
+    }
+    /// Copies the frame's pixels onto the canvas at the frame's offset, replacing the pixels underneath without blending.
+    fn compose(&mut self, frame: &AnimFrame<'_>) {
+        let row_len = packed_stride(frame.layout, frame.width);
+        let dst_stride = self.width as usize * 4;
+        for y in 0..frame.height as usize {
+            let src = &frame.image[y * frame.stride..y * frame.stride + row_len];
+            let start = (frame.y_offset as usize + y) * dst_stride + frame.x_offset as usize * 4;
+            let dst = &mut self.canvas[start..start + frame.width as usize * 4];
+            match frame.layout {
+                PixelLayout::Rgba => dst.copy_from_slice(src),
+                PixelLayout::Rgb => {
+                    for (d, s) in dst.chunks_exact_mut(4).zip(src.chunks_exact(3)) {
+                        d[..3].copy_from_slice(s);
+                        d[3] = 255;
+                    }
+                }
+            }
+        }
+    }
+    /// Assembles all pushed frames into an animated WebP image.
+    /// libwebp gives the last frame the average duration of the others, use
+    /// [finish_at](AnimStreamEncoder::finish_at) to choose when it ends.
+    pub fn finish(self) -> Result<WebPMemory, AnimEncodeError> {
+        self.assemble()
+    }
+    /// Assembles all pushed frames into an animated WebP image, ending the last frame at the given timestamp.
+    /// The timestamp must not be smaller than the one of the last frame.
+    pub fn finish_at(self, end_timestamp: i32) -> Result<WebPMemory, AnimEncodeError> {
+        let ok = unsafe {
+            WebPAnimEncoderAdd(
+                self.encoder,
+                std::ptr::null_mut(),
+                end_timestamp as std::os::raw::c_int,
+                std::ptr::null(),
+            )
+        };
         if ok == 0 {
-            //ok == false
-            WebPAnimEncoderDelete(encoder);
-            return Err(AnimEncodeError::WebPEncodingError(pic.error_code));
+            return Err(self.last_error());
         }
-        frame_pictures.push(pic);
+        self.assemble()
     }
-    WebPAnimEncoderAdd(encoder, std::ptr::null_mut(), 0, std::ptr::null());
-
-    let mut webp_data = std::mem::MaybeUninit::<WebPData>::uninit();
-    let ok = WebPAnimEncoderAssemble(encoder, webp_data.as_mut_ptr());
-    if ok == 0 {
-        let err_ptr = WebPAnimEncoderGetError(encoder);
+    fn last_error(&self) -> AnimEncodeError {
+        let err_ptr = unsafe { WebPAnimEncoderGetError(self.encoder) };
         let string = if !err_ptr.is_null() {
             unsafe { std::ffi::CStr::from_ptr(err_ptr) }
                 .to_string_lossy()
@@ -185,29 +561,43 @@ unsafe fn anim_encode(all_frame: &AnimEncoder) -> Result<WebPMemory, AnimEncodeE
         } else {
             String::from("Unknown error")
         };
-        WebPAnimEncoderDelete(encoder);
-        return Err(AnimEncodeError::WebPAnimEncoderGetError(string));
-    }
-    WebPAnimEncoderDelete(encoder);
-    let mux = WebPMuxCreateInternal(webp_data.as_ptr(), 1, mux_abi_version);
-    let mux_error = WebPMuxSetAnimationParams(mux, &all_frame.muxparams);
-    if mux_error != WebPMuxError::WEBP_MUX_OK {
-        return Err(AnimEncodeError::WebPMuxError(mux_error));
-    }
-    let mut raw_data: WebPData = webp_data.assume_init();
-    WebPDataClear(&mut raw_data);
-    let mut webp_data = std::mem::MaybeUninit::<WebPData>::uninit();
-    WebPMuxAssemble(mux, webp_data.as_mut_ptr());
-    WebPMuxDelete(mux);
-    let raw_data: WebPData = webp_data.assume_init();
-    Ok(WebPMemory(raw_data.bytes as *mut u8, raw_data.size))
+        AnimEncodeError::WebPAnimEncoderGetError(string)
+    }
+    fn assemble(self) -> Result<WebPMemory, AnimEncodeError> {
+        let assembled = unsafe {
+            let mut webp_data = WebPData::default();
+            let ok = WebPAnimEncoderAssemble(self.encoder, &mut webp_data);
+            if ok == 0 {
+                return Err(self.last_error());
+            }
+            WebPMemory(webp_data.bytes as *mut u8, webp_data.size)
+        };
+        let mut mux = Mux::from_data(&assembled).map_err(AnimEncodeError::WebPMuxError)?;
+        drop(assembled);
+        mux.set_animation_params(&self.muxparams)
+            .and_then(|_| {
+                mux.set_metadata_chunks(
+                    self.icc_profile.as_deref(),
+                    self.exif.as_deref(),
+                    self.xmp.as_deref(),
+                )
+            })
+            .and_then(|_| mux.assemble())
+            .map_err(AnimEncodeError::WebPMuxError)
+    }
+}
+
+impl Drop for AnimStreamEncoder {
+    fn drop(&mut self) {
+        unsafe { WebPAnimEncoderDelete(self.encoder) }
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
     use crate::shared::PixelLayout;
-    use crate::AnimDecoder;
+    use crate::{AnimDecoder, BitstreamFeatures, BitstreamFormat};
 
     fn default_config() -> WebPConfig {
         let mut config = unsafe { std::mem::zeroed() };
@@ -246,6 +636,18 @@ mod tests {
         assert_eq!(f_rgba.get_time_ms(), 200);
     }
 
+    #[cfg(feature = "img")]
+    #[test]
+    fn test_animframe_from_gray_image() {
+        use image::{DynamicImage, ImageBuffer, Luma};
+
+        let gray = DynamicImage::ImageLuma16(ImageBuffer::from_pixel(2, 1, Luma([257 * 9])));
+        let frame = AnimFrame::from_image(&gray, 10).unwrap();
+        assert_eq!(frame.get_layout(), PixelLayout::Rgb);
+        assert_eq!(frame.get_image(), &[9; 6]);
+        assert_eq!(frame.get_time_ms(), 10);
+    }
+
     #[test]
     fn test_animencoder_add_and_configure() {
         let config = default_config();
@@ -265,6 +667,309 @@ mod tests {
         assert_eq!(encoder.muxparams.bgcolor, expected_bg);
     }
 
+    #[test]
+    fn test_owned_animframe_into_vec_keeps_buffer() {
+        let img = vec![1u8, 2, 3, 4, 5, 6];
+        let ptr = img.as_ptr();
+        let frame = OwnedAnimFrame::from_vec(img, PixelLayout::Rgb, 2, 1, 10);
+        assert_eq!(frame.get_time_ms(), 10);
+        let v = frame.into_vec();
+        assert_eq!(v.as_ptr(), ptr);
+        assert_eq!(v, [1, 2, 3, 4, 5, 6]);
+    }
+
+    #[test]
+    fn test_animframe_into_owned() {
+        let owned = {
+            let img = vec![1u8, 2, 3, 4];
+            AnimFrame::from_rgba(&img, 1, 1, 5).into_owned()
+        };
+        assert_eq!(owned.get_image(), &[1, 2, 3, 4]);
+        assert_eq!(owned.get_layout(), PixelLayout::Rgba);
+
+        // A frame-specific configuration is kept.
+        let mut config = default_config();
+        config.lossless = 1;
+        let owned = {
+            let img = vec![1u8, 2, 3, 4];
+            AnimFrame::new(&img, PixelLayout::Rgba, 1, 1, 5, Some(&config)).into_owned()
+        };
+        assert_eq!(owned.config.map(|c| c.lossless), Some(1));
+    }
+
+    #[test]
+    fn test_animencoder_owned_frames_in_loop() {
+        let config = default_config();
+        let mut encoder = AnimEncoder::new(2, 2, &config);
+        for i in 0..3u8 {
+            let img = vec![i * 50; 2 * 2 * 4];
+            encoder.add_frame(OwnedAnimFrame::from_vec(
+                img,
+                PixelLayout::Rgba,
+                2,
+                2,
+                i as i32 * 100,
+            ));
+        }
+        let webp = encoder.try_encode().unwrap();
+        let decoded = AnimDecoder::new(&webp).decode().unwrap();
+        assert_eq!(decoded.len(), 3);
+    }
+
+    #[test]
+    fn test_animstreamencoder_push_and_finish() {
+        let config = default_config();
+        let mut encoder = AnimStreamEncoder::new(4, 4, &config).unwrap();
+        encoder.set_loop_count(2);
+        for i in 0..5 {
+            // The buffer is dropped right after it was pushed.
+            let img = vec![(i * 40) as u8; 4 * 4 * 3];
+            encoder
+                .push_frame(&AnimFrame::from_rgb(&img, 4, 4, i * 100))
+                .unwrap();
+        }
+        let webp = encoder.finish().unwrap();
+        let decoded = AnimDecoder::new(&webp).decode().unwrap();
+        assert_eq!(decoded.len(), 5);
+        assert_eq!(decoded.loop_count, 2);
+        // The decoder reports the time at which a frame ends.
+        assert_eq!(decoded.get_frame(3).unwrap().get_time_ms(), 400);
+    }
+
+    #[test]
+    fn test_animstreamencoder_finish_at() {
+        let config = default_config();
+        let img = [0u8, 100, 200, 0, 100, 200];
+        let mut encoder = AnimStreamEncoder::new(2, 1, &config).unwrap();
+        encoder
+            .push_frame(&AnimFrame::from_rgb(&img, 2, 1, 0))
+            .unwrap();
+        encoder
+            .push_frame(&AnimFrame::from_rgb(&[255; 6], 2, 1, 100))
+            .unwrap();
+        let webp = encoder.finish_at(1100).unwrap();
+        let decoded = AnimDecoder::new(&webp).decode().unwrap();
+        assert_eq!(decoded.len(), 2);
+        // The last frame lasts from 100 to 1100 ms.
+        assert_eq!(decoded.get_frame(1).unwrap().get_time_ms(), 1100);
+        let mux = crate::Mux::from_data(&webp).unwrap();
+        assert_eq!(mux.get_frame(1).unwrap().params.duration, 1000);
+
+        let mut encoder = AnimStreamEncoder::new(2, 1, &config).unwrap();
+        encoder
+            .push_frame(&AnimFrame::from_rgb(&img, 2, 1, 100))
+            .unwrap();
+        assert!(matches!(
+            encoder.finish_at(50),
+            Err(AnimEncodeError::WebPAnimEncoderGetError(_))
+        ));
+    }
+
+    #[test]
+    fn test_animstreamencoder_rejects_decreasing_timestamps() {
+        let config = default_config();
+        let mut encoder = AnimStreamEncoder::new(1, 1, &config).unwrap();
+        let img = [0u8; 3];
+        encoder
+            .push_frame(&AnimFrame::from_rgb(&img, 1, 1, 100))
+            .unwrap();
+        assert!(encoder
+            .push_frame(&AnimFrame::from_rgb(&img, 1, 1, 50))
+            .is_err());
+    }
+
+    #[test]
+    fn test_animencoderoptions_default_matches_libwebp() {
+        let options = AnimEncoderOptions::default();
+        assert!(!options.minimize_size);
+        assert!(!options.allow_mixed);
+        assert!(!options.verbose);
+        let raw = options.to_raw();
+        assert_eq!(raw.kmin, options.kmin);
+        assert_eq!(raw.kmax, options.kmax);
+
+        let options = options.keyframe_interval(3, 5);
+        assert_eq!((options.kmin, options.kmax), (3, 5));
+    }
+
+    #[test]
+    fn test_animencoder_with_options() {
+        let config = default_config();
+        // A horizontal gradient with a small square moving across it, so that libwebp encodes
+        // only the changed area of frames which aren't key-frames.
+        let frames: Vec<Vec<u8>> = (0..4u32)
+            .map(|i| {
+                (0..16 * 16u32)
+                    .flat_map(|p| {
+                        let (x, y) = (p % 16, p / 16);
+                        if (i * 4..i * 4 + 4).contains(&x) && y < 4 {
+                            [255, 0, 0]
+                        } else {
+                            [x as u8 * 16, 0, 0]
+                        }
+                    })
+                    .collect()
+            })
+            .collect();
+        let encode = |options: AnimEncoderOptions| {
+            let mut encoder = AnimEncoder::new(16, 16, &config);
+            encoder.set_options(options);
+            for (i, img) in frames.iter().enumerate() {
+                encoder.add_frame(AnimFrame::from_rgb(img, 16, 16, i as i32 * 100));
+            }
+            encoder.try_encode().unwrap()
+        };
+        // The size and format of every frame's bitstream.
+        let frames_of = |webp: &[u8]| -> Vec<_> {
+            let mux = Mux::from_data(webp).unwrap();
+            assert_eq!(mux.num_frames().unwrap(), 4);
+            (0..4)
+                .map(|i| {
+                    let frame = mux.get_frame(i).unwrap();
+                    let features = BitstreamFeatures::new(&frame.bitstream).unwrap();
+                    (features.width(), features.height(), features.format())
+                })
+                .collect()
+        };
+
+        let default = encode(AnimEncoderOptions::default());
+        let default_frames = frames_of(&default);
+        assert!(default_frames[1..]
+            .iter()
+            .all(|&(w, h, _)| w < 16 && h < 16));
+        assert!(default_frames
+            .iter()
+            .all(|(_, _, format)| matches!(format, Some(BitstreamFormat::Lossy))));
+
+        let minimized = encode(AnimEncoderOptions {
+            minimize_size: true,
+            ..Default::default()
+        });
+        assert!(minimized.len() <= default.len());
+
+        // Every frame is a key-frame covering the whole canvas.
+        let keyframes = encode(AnimEncoderOptions::default().keyframe_interval(0, 1));
+        assert!(frames_of(&keyframes)
+            .iter()
+            .all(|&(w, h, _)| (w, h) == (16, 16)));
+
+        // Mixed encoding picks lossless for some of these few-colored frames.
+        let mixed = encode(AnimEncoderOptions {
+            allow_mixed: true,
+            ..Default::default()
+        });
+        assert!(frames_of(&mixed)
+            .iter()
+            .any(|(_, _, format)| matches!(format, Some(BitstreamFormat::Lossless))));
+    }
+
+    #[test]
+    fn test_animstreamencoder_rejects_invalid_frames() {
+        let config = default_config();
+        let mut encoder = AnimStreamEncoder::new(4, 4, &config).unwrap();
+        let img = [0u8; 4 * 4 * 4];
+
+        // Buffer too small for the frame's own dimensions.
+        let res = encoder.push_frame(&AnimFrame::from_rgba(&img[..10], 4, 4, 0));
+        assert!(matches!(res, Err(AnimEncodeError::InvalidFrame(_))));
+        // Frame larger than the canvas.
+        let big = [0u8; 5 * 5 * 4];
+        let res = encoder.push_frame(&AnimFrame::from_rgba(&big, 5, 5, 0));
+        assert!(matches!(res, Err(AnimEncodeError::InvalidFrame(_))));
+        // Frame sticking out of the canvas because of its offset.
+        let res = encoder.push_frame(&AnimFrame::from_rgba(&img, 2, 2, 0).with_offset(3, 0));
+        assert!(matches!(res, Err(AnimEncodeError::InvalidFrame(_))));
+        let res = encoder.push_frame(&AnimFrame::from_rgba(&img, 2, 2, 0).with_offset(0, u32::MAX));
+        assert!(matches!(res, Err(AnimEncodeError::InvalidFrame(_))));
+        // Empty frame.
+        let res = encoder.push_frame(&AnimFrame::from_rgba(&img, 0, 2, 0));
+        assert!(matches!(res, Err(AnimEncodeError::InvalidFrame(_))));
+        // Stride shorter than a row, or a buffer too small for the stride.
+        let res = encoder.push_frame(&AnimFrame::from_rgba(&img, 4, 4, 0).with_stride(15));
+        assert!(matches!(res, Err(AnimEncodeError::InvalidFrame(_))));
+        let res = encoder.push_frame(&AnimFrame::from_rgba(&img, 4, 4, 0).with_stride(20));
+        assert!(matches!(res, Err(AnimEncodeError::InvalidFrame(_))));
+    }
+
+    #[test]
+    fn test_animframe_with_stride() {
+        let mut config = default_config();
+        config.lossless = 1;
+        // 2x2 RGB frames with a stride of 8 bytes, the last row unpadded.
+        let padded = [10u8, 20, 30, 40, 50, 60, 0, 0, 70, 80, 90, 100, 110, 120];
+        let packed = [10u8, 20, 30, 40, 50, 60, 70, 80, 90, 100, 110, 120];
+        let mut encoder = AnimStreamEncoder::new(2, 2, &config).unwrap();
+        encoder
+            .push_frame(&AnimFrame::from_rgb(&padded, 2, 2, 0).with_stride(8))
+            .unwrap();
+        let webp = encoder.finish().unwrap();
+        let decoded = AnimDecoder::new(&webp).decode().unwrap();
+        let frame = decoded.get_frame(0).unwrap();
+        let rgb: Vec<u8> = frame
+            .get_image()
+            .chunks_exact(4)
+            .flat_map(|p| p[..3].to_vec())
+            .collect();
+        assert_eq!(rgb, packed);
+
+        let frame = AnimFrame::from_rgb(&padded, 2, 2, 0).with_stride(8);
+        assert_eq!(frame.stride(), 8);
+        assert_eq!(frame.into_owned().into_vec(), packed);
+    }
+
+    #[test]
+    fn test_animencoder_sub_canvas_frames() {
+        let mut config = default_config();
+        config.lossless = 1;
+        let mut encoder = AnimEncoder::new(4, 4, &config);
+        let red = [255u8, 0, 0, 255].repeat(16);
+        let green = [0u8, 255, 0].repeat(4);
+        encoder.add_frame(AnimFrame::from_rgba(&red, 4, 4, 0));
+        encoder.add_frame(AnimFrame::from_rgb(&green, 2, 2, 100).with_offset(1, 2));
+        let webp = encoder.try_encode().unwrap();
+
+        let decoded = AnimDecoder::new(&webp).decode().unwrap();
+        let frame = decoded.get_frame(1).unwrap();
+        assert_eq!((frame.width(), frame.height()), (4, 4));
+        for (i, pixel) in frame.get_image().chunks_exact(4).enumerate() {
+            let (x, y) = (i % 4, i / 4);
+            if (1..3).contains(&x) && (2..4).contains(&y) {
+                assert_eq!(pixel, [0, 255, 0, 255]);
+            } else {
+                assert_eq!(pixel, [255, 0, 0, 255]);
+            }
+        }
+    }
+
+    #[test]
+    fn test_animencoder_metadata_round_trip() {
+        let config = default_config();
+        let icc = b"fake icc profile".to_vec();
+        let exif = b"Exif\0\0fake".to_vec();
+        let xmp = b"<x:xmpmeta/>".to_vec();
+        let img = [0u8; 2 * 2 * 4];
+        let mut encoder = AnimEncoder::new(2, 2, &config);
+        encoder.set_icc_profile(&icc);
+        encoder.set_exif(&exif);
+        encoder.set_xmp(&xmp);
+        encoder.add_frame(AnimFrame::from_rgba(&img, 2, 2, 0));
+        encoder.add_frame(AnimFrame::from_rgba(&img, 2, 2, 100));
+        let webp = encoder.try_encode().unwrap();
+
+        let decoded = AnimDecoder::new(&webp).decode().unwrap();
+        assert_eq!(decoded.icc_profile(), Some(&icc[..]));
+        assert_eq!(decoded.exif(), Some(&exif[..]));
+        assert_eq!(decoded.xmp(), Some(&xmp[..]));
+
+        let mut encoder = AnimEncoder::new(2, 2, &config);
+        encoder.add_frame(AnimFrame::from_rgba(&img, 2, 2, 0));
+        let webp = encoder.try_encode().unwrap();
+        let decoded = AnimDecoder::new(&webp).decode().unwrap();
+        assert!(decoded.icc_profile().is_none());
+        assert!(decoded.exif().is_none());
+        assert!(decoded.xmp().is_none());
+    }
+
     #[test]
     fn test_animencoder_encode_error_on_empty() {
         let config = default_config();
@@ -0,0 +1,188 @@
+use std::thread;
+
+use libwebp_sys::*;
+
+use crate::{Decoder, DistortionMetric, Encoder, WebPMemory};
+
+/// The kinds of encodings [Encoder::encode_auto] chooses between.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EncodeMode {
+    Lossless,
+    Lossy,
+    /// Lossless compression of slightly modified pixels, see `near_lossless` in [WebPConfig].
+    NearLossless,
+}
+
+/// Settings for [Encoder::encode_auto].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct AutoEncodeOptions {
+    /// The near-lossless preprocessing level between 0 (strongest) and 99, or `None` to skip that candidate.
+    pub near_lossless: Option<u8>,
+    /// Rejects the lossy and near-lossless candidates scoring below the given number of decibels
+    /// with the given metric, see [DistortionReport](crate::DistortionReport).
+    /// The lossless candidate is never rejected.
+    pub max_distortion: Option<(DistortionMetric, f32)>,
+    /// Encodes the candidates on separate threads.
+    pub parallel: bool,
+}
+
+impl Default for AutoEncodeOptions {
+    /// Tries a near-lossless level of 60 like `cwebp -near_lossless 60`, with no distortion limit, in parallel.
+    fn default() -> Self {
+        Self {
+            near_lossless: Some(60),
+            max_distortion: None,
+            parallel: true,
+        }
+    }
+}
+
+/// The result of [Encoder::encode_auto].
+#[derive(Debug)]
+pub struct AutoEncodeResult {
+    /// The smallest accepted encoding.
+    pub data: WebPMemory,
+    /// The mode which produced `data`.
+    pub mode: EncodeMode,
+    /// The score of `data` with the metric of `max_distortion`, if it was set.
+    pub score: Option<f32>,
+}
+
+impl Encoder<'_> {
+    /// Encodes the image losslessly, lossily and near-losslessly and returns the smallest result
+    /// which satisfies the distortion limit of the options.
+    ///
+    /// The lossy candidate uses the quality of the configuration, and the lossless ones use it as the compression effort.
+    /// Every other setting of the configuration is kept.
+    pub fn encode_auto(
+        &self,
+        config: &WebPConfig,
+        options: &AutoEncodeOptions,
+    ) -> Result<AutoEncodeResult, WebPEncodingError> {
+        let mut modes = vec![EncodeMode::Lossless, EncodeMode::Lossy];
+        if options.near_lossless.is_some() {
+            modes.push(EncodeMode::NearLossless);
+        }
+        let candidates: Vec<_> = if options.parallel {
+            thread::scope(|scope| {
+                let handles: Vec<_> = modes
+                    .iter()
+                    .map(|&mode| scope.spawn(move || self.encode_candidate(config, options, mode)))
+                    .collect();
+                handles
+                    .into_iter()
+                    .map(|handle| handle.join().expect("Encoding thread panicked"))
+                    .collect()
+            })
+        } else {
+            modes
+                .iter()
+                .map(|&mode| self.encode_candidate(config, options, mode))
+                .collect()
+        };
+
+        let mut best: Option<AutoEncodeResult> = None;
+        for candidate in candidates {
+            let candidate = candidate?;
+            let accepted = candidate.mode == EncodeMode::Lossless
+                || options
+                    .max_distortion
+                    .map(|(_, min_score)| candidate.score >= Some(min_score))
+                    .unwrap_or(true);
+            let smaller = match &best {
+                Some(best) => candidate.data.len() < best.data.len(),
+                None => true,
+            };
+            if accepted && smaller {
+                best = Some(candidate);
+            }
+        }
+        Ok(best.expect("The lossless candidate is always accepted"))
+    }
+
+    fn encode_candidate(
+        &self,
+        config: &WebPConfig,
+        options: &AutoEncodeOptions,
+        mode: EncodeMode,
+    ) -> Result<AutoEncodeResult, WebPEncodingError> {
+        let mut config = *config;
+        match mode {
+            EncodeMode::Lossless => {
+                config.lossless = 1;
+                config.near_lossless = 100;
+            }
+            EncodeMode::Lossy => config.lossless = 0,
+            EncodeMode::NearLossless => {
+                config.lossless = 1;
+                config.near_lossless = options.near_lossless.map_or(100, i32::from);
+            }
+        }
+        let data = self.encode_advanced(&config)?;
+        let score = match options.max_distortion {
+            Some((metric, _)) => {
+                let decoded = Decoder::new(&data)
+                    .decode()
+                    .ok_or(WebPEncodingError::VP8_ENC_ERROR_BAD_WRITE)?;
+                Some(self.compare(&decoded, metric)?.all)
+            }
+            None => None,
+        };
+        Ok(AutoEncodeResult { data, mode, score })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::shared::noise;
+    use crate::CompressionFormat;
+
+    #[test]
+    fn test_encode_auto_flat_image() {
+        // A few flat colors compress best losslessly.
+        let image: Vec<u8> = (0..64 * 64)
+            .flat_map(|i| {
+                if i % 64 < 32 {
+                    [255, 0, 0]
+                } else {
+                    [0, 0, 255]
+                }
+            })
+            .collect();
+        let encoder = Encoder::from_rgb(&image, 64, 64);
+        let config = WebPConfig::new().unwrap();
+        let result = encoder
+            .encode_auto(&config, &AutoEncodeOptions::default())
+            .unwrap();
+        assert_ne!(result.mode, EncodeMode::Lossy);
+        assert_eq!(result.score, None);
+        assert!(result.data.len() <= encoder.encode(75.0).len());
+        assert!(result.data.len() <= encoder.encode_lossless().len());
+    }
+
+    #[test]
+    fn test_encode_auto_photo() {
+        let image = noise(64 * 64 * 3);
+        let encoder = Encoder::from_rgb(&image, 64, 64);
+        let config = WebPConfig::new().unwrap();
+        let options = AutoEncodeOptions {
+            parallel: false,
+            ..Default::default()
+        };
+        let result = encoder.encode_auto(&config, &options).unwrap();
+        assert_eq!(result.mode, EncodeMode::Lossy);
+        let info = Decoder::new(&result.data).info().unwrap();
+        assert_eq!(info.format(), CompressionFormat::Lossy);
+
+        // A distortion limit which only the lossless encodings meet.
+        let options = AutoEncodeOptions {
+            max_distortion: Some((DistortionMetric::Psnr, 98.0)),
+            near_lossless: None,
+            ..Default::default()
+        };
+        let result = encoder.encode_auto(&config, &options).unwrap();
+        assert_eq!(result.mode, EncodeMode::Lossless);
+        assert_eq!(result.score, Some(99.0));
+    }
+}
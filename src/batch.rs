@@ -0,0 +1,330 @@
+use std::collections::VecDeque;
+#[cfg(feature = "img")]
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Receiver};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+
+use libwebp_sys::*;
+
+use crate::{Encoder, WebPMemory};
+
+/// An error of a single input of a [BatchEncoder].
+#[derive(Debug)]
+pub enum BatchError {
+    WebPEncodingError(WebPEncodingError),
+    /// The file couldn't be read or decoded.
+    #[cfg(feature = "img")]
+    ImageError(image::ImageError),
+    /// The input wasn't encoded because an earlier one failed and the batch stops on errors.
+    Cancelled,
+}
+
+enum BatchInput {
+    Encoder(Encoder<'static>),
+    #[cfg(feature = "img")]
+    Path(PathBuf),
+    /// Waits twice on the barrier before encoding, so that tests know when the job is in progress.
+    #[cfg(test)]
+    Gated(Arc<std::sync::Barrier>, Encoder<'static>),
+}
+
+/// Encodes many images with a shared configuration on a pool of threads.
+///
+/// Inputs are encoded in the order they were added, by as many threads as there are CPUs unless configured otherwise.
+pub struct BatchEncoder {
+    config: WebPConfig,
+    threads: usize,
+    stop_on_error: bool,
+    inputs: Vec<BatchInput>,
+}
+
+impl BatchEncoder {
+    pub fn new(config: &WebPConfig) -> Self {
+        Self {
+            config: *config,
+            threads: thread::available_parallelism().map_or(1, |n| n.get()),
+            stop_on_error: false,
+            inputs: Vec::new(),
+        }
+    }
+
+    /// Sets the number of threads, at least one is used.
+    pub fn with_threads(mut self, threads: usize) -> Self {
+        self.threads = threads.max(1);
+        self
+    }
+
+    /// Stops starting new encodings once one input failed.
+    /// The inputs which weren't started are reported as [BatchError::Cancelled].
+    pub fn with_stop_on_error(mut self, stop_on_error: bool) -> Self {
+        self.stop_on_error = stop_on_error;
+        self
+    }
+
+    /// Adds an image to encode with the configuration of the batch, keeping its metadata.
+    pub fn add(&mut self, encoder: Encoder<'static>) {
+        self.inputs.push(BatchInput::Encoder(encoder));
+    }
+
+    /// Adds a file to encode, which is decoded with the image crate when its turn comes.
+    /// Only the formats enabled in the image crate can be read.
+    #[cfg(feature = "img")]
+    pub fn add_path(&mut self, path: impl Into<PathBuf>) {
+        self.inputs.push(BatchInput::Path(path.into()));
+    }
+
+    pub fn len(&self) -> usize {
+        self.inputs.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.inputs.is_empty()
+    }
+
+    /// Encodes all inputs and returns their results in the order they were added.
+    pub fn encode(self) -> Vec<Result<WebPMemory, BatchError>> {
+        let mut results: Vec<_> = (0..self.inputs.len())
+            .map(|_| Err(BatchError::Cancelled))
+            .collect();
+        for (index, result) in self.encode_stream() {
+            results[index] = result;
+        }
+        results
+    }
+
+    /// Starts encoding all inputs and returns the results as soon as they are done,
+    /// together with the index of their input.
+    ///
+    /// Inputs cancelled because of [with_stop_on_error](BatchEncoder::with_stop_on_error) aren't returned.
+    /// Dropping the stream cancels the inputs which weren't started and waits for the encodings which are in progress.
+    pub fn encode_stream(self) -> BatchStream {
+        let jobs = Arc::new(Mutex::new(
+            self.inputs.into_iter().enumerate().collect::<VecDeque<_>>(),
+        ));
+        let stop = Arc::new(AtomicBool::new(false));
+        let (sender, receiver) = mpsc::channel();
+        let workers = (0..self.threads)
+            .map(|_| {
+                let jobs = Arc::clone(&jobs);
+                let stop = Arc::clone(&stop);
+                let sender = sender.clone();
+                let config = self.config;
+                let stop_on_error = self.stop_on_error;
+                thread::spawn(move || loop {
+                    if stop.load(Ordering::Relaxed) {
+                        break;
+                    }
+                    let Some((index, input)) = jobs.lock().unwrap().pop_front() else {
+                        break;
+                    };
+                    let result = encode_input(input, &config);
+                    if result.is_err() && stop_on_error {
+                        stop.store(true, Ordering::Relaxed);
+                    }
+                    if sender.send((index, result)).is_err() {
+                        break;
+                    }
+                })
+            })
+            .collect();
+        BatchStream {
+            receiver,
+            workers,
+            jobs,
+            stop,
+        }
+    }
+}
+
+fn encode_input(input: BatchInput, config: &WebPConfig) -> Result<WebPMemory, BatchError> {
+    match input {
+        BatchInput::Encoder(encoder) => encoder
+            .encode_advanced(config)
+            .map_err(BatchError::WebPEncodingError),
+        #[cfg(feature = "img")]
+        BatchInput::Path(path) => {
+            // The format is guessed from the contents, not from the file extension.
+            let data = std::fs::read(path).map_err(|error| BatchError::ImageError(error.into()))?;
+            let image = image::load_from_memory(&data).map_err(BatchError::ImageError)?;
+            let unsupported = || {
+                BatchError::ImageError(image::ImageError::Unsupported(
+                    image::error::UnsupportedError::from_format_and_kind(
+                        image::ImageFormat::WebP.into(),
+                        image::error::UnsupportedErrorKind::Color(image.color().into()),
+                    ),
+                ))
+            };
+            Encoder::from_image(&image)
+                .map_err(|_| unsupported())?
+                .encode_advanced(config)
+                .map_err(BatchError::WebPEncodingError)
+        }
+        #[cfg(test)]
+        BatchInput::Gated(barrier, encoder) => {
+            barrier.wait();
+            barrier.wait();
+            encode_input(BatchInput::Encoder(encoder), config)
+        }
+    }
+}
+
+/// The results of [BatchEncoder::encode_stream] in the order they are finished.
+pub struct BatchStream {
+    receiver: Receiver<(usize, Result<WebPMemory, BatchError>)>,
+    workers: Vec<JoinHandle<()>>,
+    jobs: Arc<Mutex<VecDeque<(usize, BatchInput)>>>,
+    stop: Arc<AtomicBool>,
+}
+
+impl BatchStream {
+    /// Returns the number of inputs whose encoding hasn't started yet.
+    pub fn pending(&self) -> usize {
+        self.jobs.lock().unwrap().len()
+    }
+}
+
+impl Iterator for BatchStream {
+    type Item = (usize, Result<WebPMemory, BatchError>);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        // The workers hold the only senders, so this ends once all of them are done.
+        self.receiver.recv().ok()
+    }
+}
+
+impl Drop for BatchStream {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+        for worker in self.workers.drain(..) {
+            let _ = worker.join();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Decoder, PixelLayout};
+
+    fn assert_send<T: Send>() {}
+
+    #[test]
+    fn test_inputs_and_results_are_send() {
+        assert_send::<Encoder<'static>>();
+        assert_send::<WebPMemory>();
+        assert_send::<BatchStream>();
+    }
+
+    fn image(value: u8) -> Encoder<'static> {
+        Encoder::from_vec(vec![value; 8 * 8 * 3], PixelLayout::Rgb, 8, 8)
+    }
+
+    #[test]
+    fn test_batch_encode_in_order() {
+        let mut config = WebPConfig::new().unwrap();
+        config.lossless = 1;
+        let mut batch = BatchEncoder::new(&config).with_threads(3);
+        for value in 0..10 {
+            batch.add(image(value * 20));
+        }
+        assert_eq!(batch.len(), 10);
+        let results = batch.encode();
+        assert_eq!(results.len(), 10);
+        for (value, result) in results.into_iter().enumerate() {
+            let data = result.unwrap();
+            let decoded = Decoder::new(&data).decode().unwrap();
+            assert_eq!(&*decoded, &[value as u8 * 20; 8 * 8 * 3][..]);
+        }
+    }
+
+    #[test]
+    fn test_batch_encode_stream_and_stop_on_error() {
+        let config = WebPConfig::new().unwrap();
+        let mut batch = BatchEncoder::new(&config).with_threads(2);
+        for value in 0..4 {
+            batch.add(image(value));
+        }
+        let mut indices: Vec<_> = batch
+            .encode_stream()
+            .map(|(index, result)| {
+                assert!(result.is_ok());
+                index
+            })
+            .collect();
+        indices.sort();
+        assert_eq!(indices, [0, 1, 2, 3]);
+
+        // An empty image fails to encode, the inputs after it are cancelled.
+        let mut batch = BatchEncoder::new(&config)
+            .with_threads(1)
+            .with_stop_on_error(true);
+        batch.add(image(1));
+        batch.add(Encoder::from_vec(Vec::new(), PixelLayout::Rgb, 0, 0));
+        batch.add(image(2));
+        let results = batch.encode();
+        assert!(results[0].is_ok());
+        assert!(matches!(
+            results[1],
+            Err(BatchError::WebPEncodingError(
+                WebPEncodingError::VP8_ENC_ERROR_BAD_DIMENSION
+            ))
+        ));
+        assert!(matches!(results[2], Err(BatchError::Cancelled)));
+    }
+
+    #[test]
+    fn test_batch_drop_stream_cancels_pending() {
+        let config = WebPConfig::new().unwrap();
+        let barrier = Arc::new(std::sync::Barrier::new(2));
+        let mut batch = BatchEncoder::new(&config).with_threads(1);
+        batch.add(image(0));
+        batch
+            .inputs
+            .push(BatchInput::Gated(Arc::clone(&barrier), image(1)));
+        for value in 2..50 {
+            batch.add(image(value));
+        }
+        let mut stream = batch.encode_stream();
+        assert_eq!(stream.next().unwrap().0, 0);
+        // The worker holds the second input until it is released.
+        barrier.wait();
+        assert_eq!(stream.pending(), 48);
+
+        let jobs = Arc::clone(&stream.jobs);
+        let stop = Arc::clone(&stream.stop);
+        let dropping = thread::spawn(move || drop(stream));
+        // Finish the encoding in progress only once the stream is being dropped.
+        while !stop.load(Ordering::Relaxed) {
+            thread::yield_now();
+        }
+        barrier.wait();
+        // Dropping joins the worker, so no other input was started and none will be.
+        dropping.join().unwrap();
+        assert_eq!(jobs.lock().unwrap().len(), 48);
+    }
+
+    #[cfg(feature = "img")]
+    #[test]
+    fn test_batch_encode_paths() {
+        let dir = std::env::temp_dir().join(format!("webp-batch-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let png = dir.join("image.png");
+        image::RgbImage::from_pixel(4, 4, [10, 20, 30].into())
+            .save(&png)
+            .unwrap();
+
+        let mut config = WebPConfig::new().unwrap();
+        config.lossless = 1;
+        let mut batch = BatchEncoder::new(&config);
+        batch.add_path(&png);
+        batch.add_path(dir.join("missing.png"));
+        let results = batch.encode();
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        let decoded = Decoder::new(results[0].as_ref().unwrap()).decode().unwrap();
+        assert_eq!(&*decoded, &[10, 20, 30].repeat(16)[..]);
+        assert!(matches!(results[1], Err(BatchError::ImageError(_))));
+    }
+}
@@ -0,0 +1,775 @@
+//! A parser and validator for the RIFF container of WebP files, written in safe Rust.
+//! It doesn't allocate and doesn't depend on libwebp, which makes it a cheap first check for untrusted input.
+//! Only the container and the headers of the image bitstreams are checked, the compressed data itself is not decoded.
+
+use std::fmt::{Display, Formatter};
+
+const VP8X_FLAG_ANIMATION: u8 = 0x02;
+const VP8X_FLAG_XMP: u8 = 0x04;
+const VP8X_FLAG_EXIF: u8 = 0x08;
+const VP8X_FLAG_ALPHA: u8 = 0x10;
+const VP8X_FLAG_ICCP: u8 = 0x20;
+
+/// An error found while validating a WebP container.
+/// All offsets are in bytes from the start of the file.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum ContainerError {
+    /// The data ends before the structure starting at `offset` is complete.
+    Truncated { offset: usize },
+    /// The file doesn't start with `RIFF`.
+    NotRiff,
+    /// The RIFF form type isn't `WEBP`.
+    NotWebP,
+    /// The size in the RIFF header is too small or odd.
+    InvalidRiffSize { size: u32 },
+    /// The chunk at `offset` has an odd size but its padding byte is missing.
+    MissingPadding { offset: usize },
+    /// A chunk appears where it is not allowed, e.g. a second image or an `ALPH` chunk in an animation.
+    UnexpectedChunk { fourcc: [u8; 4], offset: usize },
+    /// A chunk required by the file's features is missing.
+    MissingChunk { fourcc: [u8; 4] },
+    /// The still image or the animation frame at `offset` contains no image bitstream,
+    /// or the animation described by the `VP8X` chunk at `offset` has no frames.
+    MissingImage { offset: usize },
+    /// The contents of the chunk at `offset` are invalid.
+    InvalidChunk {
+        fourcc: [u8; 4],
+        offset: usize,
+        reason: &'static str,
+    },
+    /// A flag of the `VP8X` chunk doesn't match the chunks present in the file.
+    ///
+    /// The ICC profile, EXIF and XMP flags must be set exactly when their chunks are present.
+    /// The alpha flag must be set if an image or frame has alpha, but it may be set without any,
+    /// as it is only a hint for decoders.
+    FlagMismatch { flag: &'static str },
+}
+
+impl Display for ContainerError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let name = |fourcc: &[u8; 4]| String::from_utf8_lossy(fourcc).into_owned();
+        match self {
+            ContainerError::Truncated { offset } => {
+                write!(f, "data is truncated at offset {}", offset)
+            }
+            ContainerError::NotRiff => write!(f, "not a RIFF file"),
+            ContainerError::NotWebP => write!(f, "not a WebP file"),
+            ContainerError::InvalidRiffSize { size } => write!(f, "invalid RIFF size {}", size),
+            ContainerError::MissingPadding { offset } => {
+                write!(
+                    f,
+                    "padding byte of the chunk at offset {} is missing",
+                    offset
+                )
+            }
+            ContainerError::UnexpectedChunk { fourcc, offset } => {
+                write!(
+                    f,
+                    "unexpected {:?} chunk at offset {}",
+                    name(fourcc),
+                    offset
+                )
+            }
+            ContainerError::MissingChunk { fourcc } => {
+                write!(f, "missing {:?} chunk", name(fourcc))
+            }
+            ContainerError::MissingImage { offset } => {
+                write!(f, "no image bitstream in the chunk at offset {}", offset)
+            }
+            ContainerError::InvalidChunk {
+                fourcc,
+                offset,
+                reason,
+            } => write!(
+                f,
+                "invalid {:?} chunk at offset {}: {}",
+                name(fourcc),
+                offset,
+                reason
+            ),
+            ContainerError::FlagMismatch { flag } => {
+                write!(f, "the {} flag doesn't match the chunks in the file", flag)
+            }
+        }
+    }
+}
+
+impl std::error::Error for ContainerError {}
+
+/// A chunk of a WebP file.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Chunk<'a> {
+    fourcc: [u8; 4],
+    offset: usize,
+    data: &'a [u8],
+}
+
+impl<'a> Chunk<'a> {
+    /// Returns the FourCC identifying the chunk, e.g. `*b"VP8 "`.
+    pub fn fourcc(&self) -> [u8; 4] {
+        self.fourcc
+    }
+
+    /// Returns the offset of the chunk header from the start of the file.
+    pub fn offset(&self) -> usize {
+        self.offset
+    }
+
+    /// Returns the size of the payload, excluding the header and the padding byte.
+    pub fn size(&self) -> usize {
+        self.data.len()
+    }
+
+    /// Returns the payload of the chunk.
+    pub fn data(&self) -> &'a [u8] {
+        self.data
+    }
+
+    /// Returns the chunks nested in an `ANMF` chunk, or `None` for other chunks.
+    pub fn sub_chunks(&self) -> Option<Chunks<'a>> {
+        if &self.fourcc != b"ANMF" || self.data.len() < 16 {
+            return None;
+        }
+        Some(Chunks {
+            data: &self.data[16..],
+            pos: 0,
+            base: self.offset + 8 + 16,
+        })
+    }
+}
+
+/// An iterator over a sequence of chunks.
+/// It stops after the first error.
+#[derive(Clone, Debug)]
+pub struct Chunks<'a> {
+    data: &'a [u8],
+    pos: usize,
+    /// Offset of `data` from the start of the file.
+    base: usize,
+}
+
+impl<'a> Iterator for Chunks<'a> {
+    type Item = Result<Chunk<'a>, ContainerError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let remaining = self.data.get(self.pos..).filter(|r| !r.is_empty())?;
+        let offset = self.base + self.pos;
+        // Any error ends the iteration.
+        self.pos = self.data.len();
+
+        if remaining.len() < 8 {
+            return Some(Err(ContainerError::Truncated { offset }));
+        }
+        let fourcc = [remaining[0], remaining[1], remaining[2], remaining[3]];
+        let size = read_u32(&remaining[4..8]) as usize;
+        let data = match remaining[8..].get(..size) {
+            Some(data) => data,
+            None => return Some(Err(ContainerError::Truncated { offset })),
+        };
+        let padded_size = size + (size & 1);
+        if remaining.len() - 8 < padded_size {
+            return Some(Err(ContainerError::MissingPadding { offset }));
+        }
+        self.pos = offset - self.base + 8 + padded_size;
+        Some(Ok(Chunk {
+            fourcc,
+            offset,
+            data,
+        }))
+    }
+}
+
+/// A validated WebP container.
+#[derive(Copy, Clone, Debug)]
+pub struct Container<'a> {
+    /// The file up to the end given by the RIFF size.
+    data: &'a [u8],
+    extended: bool,
+    width: u32,
+    height: u32,
+    has_animation: bool,
+    frame_count: u32,
+}
+
+impl<'a> Container<'a> {
+    /// Parses and validates the container of a WebP file.
+    ///
+    /// Data after the end of the RIFF chunk is ignored, like libwebp does.
+    pub fn parse(data: &'a [u8]) -> Result<Self, ContainerError> {
+        if data.len() < 12 {
+            return Err(ContainerError::Truncated { offset: 0 });
+        }
+        if &data[0..4] != b"RIFF" {
+            return Err(ContainerError::NotRiff);
+        }
+        if &data[8..12] != b"WEBP" {
+            return Err(ContainerError::NotWebP);
+        }
+        let riff_size = read_u32(&data[4..8]);
+        if riff_size < 4 || riff_size % 2 == 1 {
+            return Err(ContainerError::InvalidRiffSize { size: riff_size });
+        }
+        let data = (riff_size as usize)
+            .checked_add(8)
+            .and_then(|end| data.get(..end))
+            .ok_or(ContainerError::Truncated { offset: 0 })?;
+
+        let mut container = Container {
+            data,
+            extended: false,
+            width: 0,
+            height: 0,
+            has_animation: false,
+            frame_count: 1,
+        };
+        let mut chunks = container.chunks();
+        let first = chunks
+            .next()
+            .ok_or(ContainerError::MissingImage { offset: 0 })??;
+        match &first.fourcc {
+            b"VP8 " | b"VP8L" => {
+                (container.width, container.height) = image_size(&first)?;
+                if let Some(chunk) = chunks.next() {
+                    let chunk = chunk?;
+                    return Err(ContainerError::UnexpectedChunk {
+                        fourcc: chunk.fourcc,
+                        offset: chunk.offset,
+                    });
+                }
+            }
+            b"VP8X" => container.validate_extended(&first, chunks)?,
+            _ => {
+                return Err(ContainerError::UnexpectedChunk {
+                    fourcc: first.fourcc,
+                    offset: first.offset,
+                })
+            }
+        }
+        Ok(container)
+    }
+
+    fn validate_extended(
+        &mut self,
+        vp8x: &Chunk<'a>,
+        chunks: Chunks<'a>,
+    ) -> Result<(), ContainerError> {
+        let p = vp8x.data;
+        if p.len() < 10 {
+            return Err(invalid(vp8x, "VP8X chunk is too small"));
+        }
+        let flags = p[0];
+        self.extended = true;
+        self.width = read_u24(&p[4..7]) + 1;
+        self.height = read_u24(&p[7..10]) + 1;
+        if u64::from(self.width) * u64::from(self.height) > u64::from(u32::MAX) {
+            return Err(invalid(vp8x, "canvas is too large"));
+        }
+        self.has_animation = flags & VP8X_FLAG_ANIMATION != 0;
+        self.frame_count = 0;
+
+        let mut has_iccp = false;
+        let mut has_exif = false;
+        let mut has_xmp = false;
+        let mut has_anim = false;
+        let mut has_alph = false;
+        let mut has_alpha = false;
+        let mut has_image = false;
+        for chunk in chunks {
+            let chunk = chunk?;
+            let unexpected = ContainerError::UnexpectedChunk {
+                fourcc: chunk.fourcc,
+                offset: chunk.offset,
+            };
+            match &chunk.fourcc {
+                b"VP8X" => return Err(unexpected),
+                b"ICCP" => {
+                    if has_iccp {
+                        return Err(unexpected);
+                    }
+                    has_iccp = true;
+                }
+                b"ANIM" => {
+                    if !self.has_animation || has_anim {
+                        return Err(unexpected);
+                    }
+                    if chunk.data.len() < 6 {
+                        return Err(invalid(&chunk, "ANIM chunk is too small"));
+                    }
+                    has_anim = true;
+                }
+                b"ANMF" => {
+                    if !has_anim {
+                        return Err(unexpected);
+                    }
+                    has_alpha |= self.validate_frame(&chunk)?;
+                    self.frame_count += 1;
+                }
+                b"ALPH" => {
+                    if self.has_animation || has_alph || has_image {
+                        return Err(unexpected);
+                    }
+                    has_alph = true;
+                    has_alpha = true;
+                }
+                b"VP8 " | b"VP8L" => {
+                    if self.has_animation || has_image || (has_alph && &chunk.fourcc == b"VP8L") {
+                        return Err(unexpected);
+                    }
+                    if image_size(&chunk)? != (self.width, self.height) {
+                        return Err(invalid(&chunk, "image size doesn't match the canvas"));
+                    }
+                    has_alpha |= has_vp8l_alpha(&chunk);
+                    has_image = true;
+                    self.frame_count = 1;
+                }
+                b"EXIF" => has_exif = true,
+                b"XMP " => has_xmp = true,
+                // Unknown chunks are allowed anywhere.
+                _ => {}
+            }
+        }
+
+        for (flag, present, name) in [
+            (VP8X_FLAG_ICCP, has_iccp, "ICC profile"),
+            (VP8X_FLAG_EXIF, has_exif, "EXIF"),
+            (VP8X_FLAG_XMP, has_xmp, "XMP"),
+        ] {
+            if (flags & flag != 0) != present {
+                return Err(ContainerError::FlagMismatch { flag: name });
+            }
+        }
+        if has_alpha && flags & VP8X_FLAG_ALPHA == 0 {
+            return Err(ContainerError::FlagMismatch { flag: "alpha" });
+        }
+        if self.has_animation && !has_anim {
+            return Err(ContainerError::MissingChunk { fourcc: *b"ANIM" });
+        }
+        if self.has_animation && self.frame_count == 0 {
+            return Err(ContainerError::MissingImage {
+                offset: vp8x.offset,
+            });
+        }
+        if !self.has_animation && !has_image {
+            return Err(ContainerError::MissingImage {
+                offset: vp8x.offset,
+            });
+        }
+        Ok(())
+    }
+
+    /// Validates an `ANMF` chunk and returns whether the frame has alpha.
+    fn validate_frame(&self, anmf: &Chunk<'a>) -> Result<bool, ContainerError> {
+        let p = anmf.data;
+        if p.len() < 16 {
+            return Err(invalid(anmf, "ANMF chunk is too small"));
+        }
+        let x = read_u24(&p[0..3]) * 2;
+        let y = read_u24(&p[3..6]) * 2;
+        let width = read_u24(&p[6..9]) + 1;
+        let height = read_u24(&p[9..12]) + 1;
+        if x + width > self.width || y + height > self.height {
+            return Err(invalid(anmf, "frame doesn't fit in the canvas"));
+        }
+
+        let mut has_alph = false;
+        let mut has_alpha = false;
+        let mut has_image = false;
+        for chunk in anmf.sub_chunks().into_iter().flatten() {
+            let chunk = chunk?;
+            let unexpected = ContainerError::UnexpectedChunk {
+                fourcc: chunk.fourcc,
+                offset: chunk.offset,
+            };
+            match &chunk.fourcc {
+                b"ALPH" => {
+                    if has_alph || has_image {
+                        return Err(unexpected);
+                    }
+                    has_alph = true;
+                }
+                b"VP8 " | b"VP8L" => {
+                    if has_image || (has_alph && &chunk.fourcc == b"VP8L") {
+                        return Err(unexpected);
+                    }
+                    if image_size(&chunk)? != (width, height) {
+                        return Err(invalid(&chunk, "image size doesn't match the frame"));
+                    }
+                    has_alpha = has_alph || has_vp8l_alpha(&chunk);
+                    has_image = true;
+                }
+                b"VP8X" | b"ICCP" | b"ANIM" | b"ANMF" => return Err(unexpected),
+                _ => {}
+            }
+        }
+        if !has_image {
+            return Err(ContainerError::MissingImage {
+                offset: anmf.offset,
+            });
+        }
+        Ok(has_alpha)
+    }
+
+    /// Returns an iterator over the top-level chunks of the file.
+    pub fn chunks(&self) -> Chunks<'a> {
+        Chunks {
+            data: &self.data[12..],
+            pos: 0,
+            base: 12,
+        }
+    }
+
+    /// Returns the size in the RIFF header, which covers everything after the first 8 bytes.
+    pub fn riff_size(&self) -> u32 {
+        read_u32(&self.data[4..8])
+    }
+
+    /// Returns the width of the canvas in pixels.
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    /// Returns the height of the canvas in pixels.
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    /// Returns true if the file uses the extended format, i.e. starts with a `VP8X` chunk.
+    pub fn is_extended(&self) -> bool {
+        self.extended
+    }
+
+    /// Returns true if the file is animated.
+    pub fn has_animation(&self) -> bool {
+        self.has_animation
+    }
+
+    /// Returns the number of frames, which is 1 for a still image.
+    pub fn frame_count(&self) -> u32 {
+        self.frame_count
+    }
+}
+
+fn invalid(chunk: &Chunk<'_>, reason: &'static str) -> ContainerError {
+    ContainerError::InvalidChunk {
+        fourcc: chunk.fourcc,
+        offset: chunk.offset,
+        reason,
+    }
+}
+
+/// Returns the dimensions stored in the header of a VP8 or VP8L bitstream.
+fn image_size(chunk: &Chunk<'_>) -> Result<(u32, u32), ContainerError> {
+    let p = chunk.data;
+    if &chunk.fourcc == b"VP8L" {
+        if p.len() < 5 {
+            return Err(invalid(chunk, "VP8L bitstream is too short"));
+        }
+        if p[0] != 0x2f {
+            return Err(invalid(chunk, "invalid VP8L signature"));
+        }
+        let bits = read_u32(&p[1..5]);
+        if bits >> 29 != 0 {
+            return Err(invalid(chunk, "unsupported VP8L version"));
+        }
+        Ok(((bits & 0x3fff) + 1, ((bits >> 14) & 0x3fff) + 1))
+    } else {
+        if p.len() < 10 {
+            return Err(invalid(chunk, "VP8 bitstream is too short"));
+        }
+        let frame_tag = read_u24(&p[0..3]);
+        if frame_tag & 1 != 0 {
+            return Err(invalid(chunk, "VP8 bitstream is not a key frame"));
+        }
+        if (frame_tag >> 5) as usize > p.len() - 10 {
+            return Err(invalid(chunk, "VP8 partition exceeds the chunk"));
+        }
+        if p[3..6] != [0x9d, 0x01, 0x2a] {
+            return Err(invalid(chunk, "invalid VP8 start code"));
+        }
+        let width = u32::from(u16::from_le_bytes([p[6], p[7]]) & 0x3fff);
+        let height = u32::from(u16::from_le_bytes([p[8], p[9]]) & 0x3fff);
+        if width == 0 || height == 0 {
+            return Err(invalid(chunk, "VP8 image has a zero dimension"));
+        }
+        Ok((width, height))
+    }
+}
+
+/// Returns the `alpha_is_used` hint of a VP8L header which [image_size] has already checked.
+fn has_vp8l_alpha(chunk: &Chunk<'_>) -> bool {
+    &chunk.fourcc == b"VP8L" && read_u32(&chunk.data[1..5]) & (1 << 28) != 0
+}
+
+fn read_u24(b: &[u8]) -> u32 {
+    u32::from_le_bytes([b[0], b[1], b[2], 0])
+}
+
+fn read_u32(b: &[u8]) -> u32 {
+    u32::from_le_bytes([b[0], b[1], b[2], b[3]])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{AnimEncoder, AnimFrame, Encoder, WebPConfig};
+
+    fn lossless() -> Vec<u8> {
+        let image = [1u8, 2, 3].repeat(4 * 3);
+        Encoder::from_rgb(&image, 4, 3).encode_lossless().to_vec()
+    }
+
+    fn chunk(fourcc: &[u8; 4], payload: &[u8]) -> Vec<u8> {
+        let mut chunk = fourcc.to_vec();
+        chunk.extend_from_slice(&(payload.len() as u32).to_le_bytes());
+        chunk.extend_from_slice(payload);
+        if payload.len() % 2 == 1 {
+            chunk.push(0);
+        }
+        chunk
+    }
+
+    fn riff(chunks: &[u8]) -> Vec<u8> {
+        let mut data = b"RIFF".to_vec();
+        data.extend_from_slice(&(chunks.len() as u32 + 4).to_le_bytes());
+        data.extend_from_slice(b"WEBP");
+        data.extend_from_slice(chunks);
+        data
+    }
+
+    #[test]
+    fn test_parse_simple() {
+        let data = lossless();
+        let container = Container::parse(&data).unwrap();
+        assert_eq!((container.width(), container.height()), (4, 3));
+        assert!(!container.is_extended());
+        assert!(!container.has_animation());
+        assert_eq!(container.frame_count(), 1);
+        assert_eq!(container.riff_size() as usize, data.len() - 8);
+
+        let chunks: Vec<_> = container.chunks().map(Result::unwrap).collect();
+        assert_eq!(chunks.len(), 1);
+        assert_eq!(&chunks[0].fourcc(), b"VP8L");
+        assert_eq!(chunks[0].offset(), 12);
+        assert_eq!(chunks[0].size(), data.len() - 20);
+
+        let lossy = Encoder::from_rgb(&[9u8; 5 * 5 * 3], 5, 5).encode(50.0);
+        let container = Container::parse(&lossy).unwrap();
+        assert_eq!((container.width(), container.height()), (5, 5));
+    }
+
+    #[test]
+    fn test_parse_extended_and_animated() {
+        let image = [1u8, 2, 3].repeat(4 * 3);
+        let mut encoder = Encoder::from_rgb(&image, 4, 3);
+        encoder.set_icc_profile(b"icc");
+        encoder.set_exif(b"odd");
+        let data = encoder.encode_lossless();
+        let container = Container::parse(&data).unwrap();
+        assert!(container.is_extended());
+        let fourccs: Vec<_> = container.chunks().map(|c| c.unwrap().fourcc()).collect();
+        assert_eq!(fourccs, [*b"VP8X", *b"ICCP", *b"VP8L", *b"EXIF"]);
+
+        let config = WebPConfig::new().unwrap();
+        let mut encoder = AnimEncoder::new(8, 8, &config);
+        for i in 0..3u8 {
+            let frame = vec![i * 100; 8 * 8 * 4];
+            encoder.add_frame(AnimFrame::from_rgba(&frame, 8, 8, i as i32 * 100).into_owned());
+        }
+        let data = encoder.encode();
+        let container = Container::parse(&data).unwrap();
+        assert!(container.has_animation());
+        assert_eq!(container.frame_count(), 3);
+        let anmf = container
+            .chunks()
+            .map(Result::unwrap)
+            .find(|c| &c.fourcc() == b"ANMF")
+            .unwrap();
+        assert!(anmf.sub_chunks().unwrap().all(|c| c.is_ok()));
+    }
+
+    #[test]
+    fn test_parse_header_errors() {
+        let data = lossless();
+        assert_eq!(
+            Container::parse(&data[..8]).unwrap_err(),
+            ContainerError::Truncated { offset: 0 }
+        );
+        let mut bad = data.clone();
+        bad[0] = b'X';
+        assert_eq!(Container::parse(&bad).unwrap_err(), ContainerError::NotRiff);
+        let mut bad = data.clone();
+        bad[8] = b'X';
+        assert_eq!(Container::parse(&bad).unwrap_err(), ContainerError::NotWebP);
+        let mut bad = data.clone();
+        bad[4..8].copy_from_slice(&3u32.to_le_bytes());
+        assert_eq!(
+            Container::parse(&bad).unwrap_err(),
+            ContainerError::InvalidRiffSize { size: 3 }
+        );
+        assert_eq!(
+            Container::parse(&data[..data.len() - 2]).unwrap_err(),
+            ContainerError::Truncated { offset: 0 }
+        );
+
+        // Trailing data is ignored.
+        let mut trailing = data.clone();
+        trailing.extend_from_slice(b"junk");
+        assert!(Container::parse(&trailing).is_ok());
+    }
+
+    #[test]
+    fn test_parse_chunk_errors() {
+        let image = &lossless()[12..];
+
+        // A chunk whose size exceeds the RIFF chunk.
+        let mut data = riff(image);
+        let len = data.len();
+        data[16..20].copy_from_slice(&(len as u32).to_le_bytes());
+        assert_eq!(
+            Container::parse(&data).unwrap_err(),
+            ContainerError::Truncated { offset: 12 }
+        );
+
+        // An odd-sized chunk without its padding byte, at the end of a frame.
+        let anmf = Chunk {
+            fourcc: *b"ANMF",
+            offset: 30,
+            data: &[[0; 16].as_slice(), b"JUNK\x01\x00\x00\x00x"].concat(),
+        };
+        let mut sub_chunks = anmf.sub_chunks().unwrap();
+        assert_eq!(
+            sub_chunks.next(),
+            Some(Err(ContainerError::MissingPadding {
+                offset: 30 + 8 + 16
+            }))
+        );
+        assert_eq!(sub_chunks.next(), None);
+
+        // Simple files contain a single image chunk.
+        let data = riff(&[image, &chunk(b"JUNK", b"ab")].concat());
+        assert_eq!(
+            Container::parse(&data).unwrap_err(),
+            ContainerError::UnexpectedChunk {
+                fourcc: *b"JUNK",
+                offset: 12 + image.len()
+            }
+        );
+
+        // A broken VP8L signature.
+        let mut broken = image.to_vec();
+        broken[8] = 0;
+        assert!(matches!(
+            Container::parse(&riff(&broken)),
+            Err(ContainerError::InvalidChunk { offset: 12, .. })
+        ));
+    }
+
+    #[test]
+    fn test_parse_extended_errors() {
+        let image = &lossless()[12..];
+        let vp8x = |flags: u8, width: u32, height: u32| {
+            let mut payload = vec![flags, 0, 0, 0];
+            payload.extend_from_slice(&(width - 1).to_le_bytes()[..3]);
+            payload.extend_from_slice(&(height - 1).to_le_bytes()[..3]);
+            chunk(b"VP8X", &payload)
+        };
+
+        let data = riff(&[&vp8x(0, 4, 3)[..], image].concat());
+        assert!(Container::parse(&data).is_ok());
+
+        let data = riff(&[&vp8x(0, 5, 3)[..], image].concat());
+        assert!(matches!(
+            Container::parse(&data),
+            Err(ContainerError::InvalidChunk { .. })
+        ));
+
+        let data = riff(&[&vp8x(VP8X_FLAG_ICCP, 4, 3)[..], image].concat());
+        assert_eq!(
+            Container::parse(&data).unwrap_err(),
+            ContainerError::FlagMismatch {
+                flag: "ICC profile"
+            }
+        );
+
+        let exif = chunk(b"EXIF", b"exif");
+        let data = riff(&[&vp8x(0, 4, 3)[..], image, &exif].concat());
+        assert_eq!(
+            Container::parse(&data).unwrap_err(),
+            ContainerError::FlagMismatch { flag: "EXIF" }
+        );
+        let data = riff(&[&vp8x(VP8X_FLAG_EXIF, 4, 3)[..], image, &exif].concat());
+        assert!(Container::parse(&data).is_ok());
+        let data = riff(&[&vp8x(VP8X_FLAG_XMP, 4, 3)[..], image].concat());
+        assert_eq!(
+            Container::parse(&data).unwrap_err(),
+            ContainerError::FlagMismatch { flag: "XMP" }
+        );
+
+        // Alpha must be flagged, but the flag alone is accepted.
+        let alph = chunk(b"ALPH", &[0]);
+        let lossy = Encoder::from_rgb(&[9u8; 4 * 3 * 3], 4, 3).encode(50.0);
+        let lossy = &lossy[12..];
+        let data = riff(&[&vp8x(0, 4, 3)[..], &alph, lossy].concat());
+        assert_eq!(
+            Container::parse(&data).unwrap_err(),
+            ContainerError::FlagMismatch { flag: "alpha" }
+        );
+        let data = riff(&[&vp8x(VP8X_FLAG_ALPHA, 4, 3)[..], &alph, lossy].concat());
+        assert!(Container::parse(&data).is_ok());
+        let data = riff(&[&vp8x(VP8X_FLAG_ALPHA, 4, 3)[..], image].concat());
+        assert!(Container::parse(&data).is_ok());
+        let transparent = Encoder::from_rgba(&[1u8, 2, 3, 4].repeat(4 * 3), 4, 3).encode_lossless();
+        let data = riff(&[&vp8x(0, 4, 3)[..], &transparent[12..]].concat());
+        assert_eq!(
+            Container::parse(&data).unwrap_err(),
+            ContainerError::FlagMismatch { flag: "alpha" }
+        );
+
+        let data = riff(&vp8x(0, 4, 3));
+        assert_eq!(
+            Container::parse(&data).unwrap_err(),
+            ContainerError::MissingImage { offset: 12 }
+        );
+
+        let data = riff(&[&vp8x(0, 4, 3)[..], image, image].concat());
+        assert!(matches!(
+            Container::parse(&data),
+            Err(ContainerError::UnexpectedChunk { fourcc, .. }) if &fourcc == b"VP8L"
+        ));
+
+        let data = riff(&[&vp8x(VP8X_FLAG_ANIMATION, 4, 3)[..], image].concat());
+        assert!(matches!(
+            Container::parse(&data),
+            Err(ContainerError::UnexpectedChunk { .. })
+        ));
+
+        // An animation without frames.
+        let anim = chunk(b"ANIM", &[0; 6]);
+        let data = riff(&[&vp8x(VP8X_FLAG_ANIMATION, 4, 3)[..], &anim].concat());
+        assert_eq!(
+            Container::parse(&data).unwrap_err(),
+            ContainerError::MissingImage { offset: 12 }
+        );
+
+        // A frame which sticks out of the canvas.
+        let mut header = vec![1, 0, 0, 0, 0, 0];
+        header.extend_from_slice(&3u32.to_le_bytes()[..3]);
+        header.extend_from_slice(&2u32.to_le_bytes()[..3]);
+        header.extend_from_slice(&[0; 4]);
+        let anmf = chunk(b"ANMF", &[&header[..], image].concat());
+        let data = riff(&[&vp8x(VP8X_FLAG_ANIMATION, 4, 3)[..], &anim, &anmf].concat());
+        assert!(matches!(
+            Container::parse(&data),
+            Err(ContainerError::InvalidChunk { fourcc, .. }) if &fourcc == b"ANMF"
+        ));
+    }
+
+    #[test]
+    fn test_container_error_display() {
+        let err = ContainerError::UnexpectedChunk {
+            fourcc: *b"VP8 ",
+            offset: 30,
+        };
+        assert_eq!(err.to_string(), "unexpected \"VP8 \" chunk at offset 30");
+    }
+}
@@ -0,0 +1,146 @@
+//! Conversions of pixels in the formats of the image crate to the 8-bit RGB(A) layouts libwebp accepts.
+//!
+//! High bit depth samples are rounded to the nearest 8-bit value, without dithering.
+//! Floating point samples are expected in [0, 1]: other values are clamped and NaN becomes 0.
+
+use std::borrow::Cow;
+
+use image::{DynamicImage, ExtendedColorType};
+
+use crate::shared::PixelLayout;
+
+/// Converts a 16-bit sample to 8 bits, rounding to the nearest value.
+pub(crate) fn u16_to_u8(sample: u16) -> u8 {
+    ((u32::from(sample) + 128) / 257) as u8
+}
+
+/// Converts a floating point sample to 8 bits, rounding to the nearest value.
+pub(crate) fn f32_to_u8(sample: f32) -> u8 {
+    // `as` maps NaN to 0.
+    (sample.clamp(0.0, 1.0) * 255.0).round() as u8
+}
+
+/// Converts interleaved samples with 1 (gray), 2 (gray and alpha), 3 (RGB) or 4 (RGBA) channels
+/// to RGB, or RGBA if there is an alpha channel.
+pub(crate) fn to_rgb<T: Copy>(
+    samples: &[T],
+    channels: usize,
+    to_u8: impl Fn(T) -> u8,
+) -> (Vec<u8>, PixelLayout) {
+    let layout = if matches!(channels, 2 | 4) {
+        PixelLayout::Rgba
+    } else {
+        PixelLayout::Rgb
+    };
+    let mut rgb =
+        Vec::with_capacity(samples.len() / channels * usize::from(layout.bytes_per_pixel()));
+    for pixel in samples.chunks_exact(channels) {
+        match *pixel {
+            [gray] => rgb.extend_from_slice(&[to_u8(gray); 3]),
+            [gray, alpha] => {
+                let gray = to_u8(gray);
+                rgb.extend_from_slice(&[gray, gray, gray, to_u8(alpha)]);
+            }
+            _ => rgb.extend(pixel.iter().map(|&sample| to_u8(sample))),
+        }
+    }
+    (rgb, layout)
+}
+
+/// Converts a buffer of the given color type to RGB(A).
+/// Returns `None` for color types which can't be converted.
+pub(crate) fn from_extended(
+    buf: &[u8],
+    color_type: ExtendedColorType,
+) -> Option<(Cow<'_, [u8]>, PixelLayout)> {
+    let u16_samples = || -> Vec<u16> {
+        buf.chunks_exact(2)
+            .map(|b| u16::from_ne_bytes([b[0], b[1]]))
+            .collect()
+    };
+    let f32_samples = || -> Vec<f32> {
+        buf.chunks_exact(4)
+            .map(|b| f32::from_ne_bytes([b[0], b[1], b[2], b[3]]))
+            .collect()
+    };
+    let (rgb, layout) = match color_type {
+        ExtendedColorType::Rgb8 => return Some((Cow::Borrowed(buf), PixelLayout::Rgb)),
+        ExtendedColorType::Rgba8 => return Some((Cow::Borrowed(buf), PixelLayout::Rgba)),
+        ExtendedColorType::L8 => to_rgb(buf, 1, |v| v),
+        ExtendedColorType::La8 => to_rgb(buf, 2, |v| v),
+        ExtendedColorType::Bgr8 | ExtendedColorType::Bgra8 => {
+            let layout = if color_type == ExtendedColorType::Bgra8 {
+                PixelLayout::Rgba
+            } else {
+                PixelLayout::Rgb
+            };
+            let mut rgb = buf.to_vec();
+            rgb.chunks_exact_mut(usize::from(layout.bytes_per_pixel()))
+                .for_each(|pixel| pixel.swap(0, 2));
+            (rgb, layout)
+        }
+        ExtendedColorType::L16 => to_rgb(&u16_samples(), 1, u16_to_u8),
+        ExtendedColorType::La16 => to_rgb(&u16_samples(), 2, u16_to_u8),
+        ExtendedColorType::Rgb16 => to_rgb(&u16_samples(), 3, u16_to_u8),
+        ExtendedColorType::Rgba16 => to_rgb(&u16_samples(), 4, u16_to_u8),
+        ExtendedColorType::Rgb32F => to_rgb(&f32_samples(), 3, f32_to_u8),
+        ExtendedColorType::Rgba32F => to_rgb(&f32_samples(), 4, f32_to_u8),
+        _ => return None,
+    };
+    Some((Cow::Owned(rgb), layout))
+}
+
+/// Converts the pixels of an image to RGB(A), borrowing them if they are RGB8 or RGBA8 already.
+/// Returns `None` for variants which can't be converted.
+pub(crate) fn from_dynamic(image: &DynamicImage) -> Option<(Cow<'_, [u8]>, PixelLayout)> {
+    let (rgb, layout) = match image {
+        DynamicImage::ImageRgb8(image) => return Some((Cow::Borrowed(image), PixelLayout::Rgb)),
+        DynamicImage::ImageRgba8(image) => return Some((Cow::Borrowed(image), PixelLayout::Rgba)),
+        DynamicImage::ImageLuma8(image) => to_rgb(image, 1, |v| v),
+        DynamicImage::ImageLumaA8(image) => to_rgb(image, 2, |v| v),
+        DynamicImage::ImageLuma16(image) => to_rgb(image, 1, u16_to_u8),
+        DynamicImage::ImageLumaA16(image) => to_rgb(image, 2, u16_to_u8),
+        DynamicImage::ImageRgb16(image) => to_rgb(image, 3, u16_to_u8),
+        DynamicImage::ImageRgba16(image) => to_rgb(image, 4, u16_to_u8),
+        DynamicImage::ImageRgb32F(image) => to_rgb(image, 3, f32_to_u8),
+        DynamicImage::ImageRgba32F(image) => to_rgb(image, 4, f32_to_u8),
+        _ => return None,
+    };
+    Some((Cow::Owned(rgb), layout))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sample_conversion() {
+        assert_eq!(u16_to_u8(0), 0);
+        assert_eq!(u16_to_u8(128), 0);
+        assert_eq!(u16_to_u8(129), 1);
+        assert_eq!(u16_to_u8(257 * 100), 100);
+        assert_eq!(u16_to_u8(u16::MAX), 255);
+        assert_eq!(f32_to_u8(-1.0), 0);
+        assert_eq!(f32_to_u8(0.5), 128);
+        assert_eq!(f32_to_u8(2.0), 255);
+        assert_eq!(f32_to_u8(f32::NAN), 0);
+    }
+
+    #[test]
+    fn test_from_extended() {
+        let (rgb, layout) = from_extended(&[1, 2], ExtendedColorType::La8).unwrap();
+        assert_eq!((&*rgb, layout), (&[1, 1, 1, 2][..], PixelLayout::Rgba));
+
+        let (rgb, layout) = from_extended(&[1, 2, 3], ExtendedColorType::Bgr8).unwrap();
+        assert_eq!((&*rgb, layout), (&[3, 2, 1][..], PixelLayout::Rgb));
+
+        let buf: Vec<u8> = [u16::MAX, 0, 257 * 7]
+            .iter()
+            .flat_map(|v| v.to_ne_bytes())
+            .collect();
+        let (rgb, layout) = from_extended(&buf, ExtendedColorType::Rgb16).unwrap();
+        assert_eq!((&*rgb, layout), (&[255, 0, 7][..], PixelLayout::Rgb));
+
+        assert!(from_extended(&[0], ExtendedColorType::L1).is_none());
+    }
+}
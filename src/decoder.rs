@@ -2,7 +2,9 @@ use std::fmt::{Debug, Error, Formatter};
 
 use libwebp_sys::*;
 
-use crate::shared::{PixelLayout, WebPImage, WebPMemory};
+use crate::demux::Demuxer;
+use crate::shared::{PixelLayout, WebPImage};
+use crate::Metadata;
 
 /// A decoder for WebP images. It uses the default configuration of libwebp.
 /// Currently, animated images are not supported.
@@ -26,44 +28,50 @@ impl<'a> Decoder<'a> {
 
         let width = features.width();
         let height = features.height();
-        let pixel_count = width * height;
-
-        let image_ptr = unsafe {
-            let mut width = width as i32;
-            let mut height = height as i32;
+        let layout = if features.has_alpha() {
+            PixelLayout::Rgba
+        } else {
+            PixelLayout::Rgb
+        };
+        // Decode straight into a Rust buffer, so the pixels can be handed over without copying them.
+        let stride = width as usize * usize::from(layout.bytes_per_pixel());
+        let mut data = vec![0u8; stride * height as usize];
 
+        let result = unsafe {
             if features.has_alpha() {
-                WebPDecodeRGBA(
+                WebPDecodeRGBAInto(
                     self.data.as_ptr(),
                     self.data.len(),
-                    &mut width as *mut _,
-                    &mut height as *mut _,
+                    data.as_mut_ptr(),
+                    data.len(),
+                    stride as i32,
                 )
             } else {
-                WebPDecodeRGB(
+                WebPDecodeRGBInto(
                     self.data.as_ptr(),
                     self.data.len(),
-                    &mut width as *mut _,
-                    &mut height as *mut _,
+                    data.as_mut_ptr(),
+                    data.len(),
+                    stride as i32,
                 )
             }
         };
 
-        if image_ptr.is_null() {
+        if result.is_null() {
             return None;
         }
 
-        let image = if features.has_alpha() {
-            let len = 4 * pixel_count as usize;
-
-            WebPImage::new(WebPMemory(image_ptr, len), PixelLayout::Rgba, width, height)
-        } else {
-            let len = 3 * pixel_count as usize;
+        Some(WebPImage::new(data, layout, width, height))
+    }
 
-            WebPImage::new(WebPMemory(image_ptr, len), PixelLayout::Rgb, width, height)
-        };
+    /// Reads the ICC profile, EXIF and XMP metadata of the image without decoding it.
+    pub fn metadata(&self) -> Option<Metadata> {
+        Metadata::new(self.data)
+    }
 
-        Some(image)
+    /// Reads the [ImageInfo] of the image without decoding it.
+    pub fn info(&self) -> Option<ImageInfo> {
+        ImageInfo::new(self.data)
     }
 }
 
@@ -145,6 +153,133 @@ pub enum BitstreamFormat {
     Lossless = 2,
 }
 
+/// Information about a still or animated WebP file, read from its container without decoding any pixels.
+///
+/// Unlike [BitstreamFeatures], it describes the whole file: the canvas, the metadata chunks and all frames.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ImageInfo {
+    width: u32,
+    height: u32,
+    has_alpha: bool,
+    has_animation: bool,
+    has_icc_profile: bool,
+    has_exif: bool,
+    has_xmp: bool,
+    frame_count: u32,
+    loop_count: u32,
+    bg_color: u32,
+    format: CompressionFormat,
+}
+
+/// How the frames of a WebP file are compressed.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum CompressionFormat {
+    Lossy,
+    Lossless,
+    /// Some frames of the animation are lossy and others lossless.
+    Mixed,
+}
+
+impl ImageInfo {
+    /// Parses the container of the given WebP file.
+    /// Returns `None` if the data isn't a complete and valid WebP file.
+    pub fn new(data: &[u8]) -> Option<Self> {
+        let demuxer = Demuxer::new(data)?;
+        let flags = demuxer.get_i(WebPFormatFeature::WEBP_FF_FORMAT_FLAGS);
+        let has_flag = |flag: WebPFeatureFlags| flags & flag as u32 != 0;
+
+        let mut has_alpha = has_flag(WebPFeatureFlags::ALPHA_FLAG);
+        let mut lossy = false;
+        let mut lossless = false;
+        demuxer.for_each_frame(|iter| {
+            has_alpha |= iter.has_alpha != 0;
+            // The fragment starts with the ALPH chunk if there is one, which is only used with VP8.
+            let fragment =
+                unsafe { std::slice::from_raw_parts(iter.fragment.bytes, iter.fragment.size) };
+            if fragment.starts_with(b"VP8L") {
+                lossless = true;
+            } else {
+                lossy = true;
+            }
+        });
+        let format = match (lossy, lossless) {
+            (true, true) => CompressionFormat::Mixed,
+            (false, true) => CompressionFormat::Lossless,
+            _ => CompressionFormat::Lossy,
+        };
+
+        Some(Self {
+            width: demuxer.get_i(WebPFormatFeature::WEBP_FF_CANVAS_WIDTH),
+            height: demuxer.get_i(WebPFormatFeature::WEBP_FF_CANVAS_HEIGHT),
+            has_alpha,
+            has_animation: has_flag(WebPFeatureFlags::ANIMATION_FLAG),
+            has_icc_profile: has_flag(WebPFeatureFlags::ICCP_FLAG),
+            has_exif: has_flag(WebPFeatureFlags::EXIF_FLAG),
+            has_xmp: has_flag(WebPFeatureFlags::XMP_FLAG),
+            frame_count: demuxer.get_i(WebPFormatFeature::WEBP_FF_FRAME_COUNT),
+            loop_count: demuxer.get_i(WebPFormatFeature::WEBP_FF_LOOP_COUNT),
+            bg_color: demuxer.get_i(WebPFormatFeature::WEBP_FF_BACKGROUND_COLOR),
+            format,
+        })
+    }
+
+    /// Returns the width of the canvas in pixels.
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    /// Returns the height of the canvas in pixels.
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    /// Returns true if the file is flagged as having transparency or any frame has an alpha channel.
+    pub fn has_alpha(&self) -> bool {
+        self.has_alpha
+    }
+
+    /// Returns true if the file is animated.
+    pub fn has_animation(&self) -> bool {
+        self.has_animation
+    }
+
+    /// Returns true if the file contains an ICC profile.
+    pub fn has_icc_profile(&self) -> bool {
+        self.has_icc_profile
+    }
+
+    /// Returns true if the file contains EXIF metadata.
+    pub fn has_exif(&self) -> bool {
+        self.has_exif
+    }
+
+    /// Returns true if the file contains XMP metadata.
+    pub fn has_xmp(&self) -> bool {
+        self.has_xmp
+    }
+
+    /// Returns the number of frames, which is 1 for still images.
+    pub fn frame_count(&self) -> u32 {
+        self.frame_count
+    }
+
+    /// Returns the number of times the animation is played, 0 means infinitely. Always 1 for still images.
+    pub fn loop_count(&self) -> u32 {
+        self.loop_count
+    }
+
+    /// Returns the background color in the byte order of the `ANIM` chunk, like `DecodeAnimImage::bg_color`.
+    /// Always 0xFFFFFFFF (opaque white) for still images.
+    pub fn bg_color(&self) -> u32 {
+        self.bg_color
+    }
+
+    /// Returns how the frames are compressed.
+    pub fn format(&self) -> CompressionFormat {
+        self.format
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -195,6 +330,88 @@ mod tests {
         assert!(image.is_some());
     }
 
+    #[test]
+    fn test_decoder_metadata() {
+        let data = minimal_webp_rgb();
+        let metadata = Decoder::new(&data).metadata().unwrap();
+        assert!(metadata.is_empty());
+        assert!(Decoder::new(&[0u8; 8]).metadata().is_none());
+    }
+
+    #[cfg(feature = "img")]
+    #[test]
+    fn test_decoded_into_image() {
+        let data = minimal_webp_rgb();
+        let decoded = Decoder::new(&data).decode().unwrap();
+        let pixels = decoded.to_vec();
+        let ptr = decoded.as_ptr();
+        let image = decoded.into_image();
+        assert_eq!((image.width(), image.height()), (1, 1));
+        assert_eq!(image.as_bytes(), &pixels[..]);
+        // The buffer is handed over, not copied.
+        assert_eq!(image.as_bytes().as_ptr(), ptr);
+    }
+
+    #[test]
+    fn test_image_info_still() {
+        let data = minimal_webp_rgb();
+        let info = Decoder::new(&data).info().unwrap();
+        assert_eq!((info.width(), info.height()), (1, 1));
+        assert!(!info.has_alpha() && !info.has_animation());
+        assert!(!info.has_icc_profile() && !info.has_exif() && !info.has_xmp());
+        assert_eq!(info.frame_count(), 1);
+        assert_eq!((info.loop_count(), info.bg_color()), (1, 0xFFFFFFFF));
+        assert_eq!(info.format(), CompressionFormat::Lossy);
+
+        let image = [10u8, 20, 30, 128].repeat(3 * 2);
+        let mut encoder = crate::Encoder::from_rgba(&image, 3, 2);
+        encoder.set_icc_profile(b"icc");
+        encoder.set_xmp(b"<xmp/>");
+        let data = encoder.encode_lossless();
+        let info = ImageInfo::new(&data).unwrap();
+        assert_eq!((info.width(), info.height()), (3, 2));
+        assert!(info.has_alpha() && info.has_icc_profile() && info.has_xmp() && !info.has_exif());
+        assert_eq!(info.format(), CompressionFormat::Lossless);
+
+        assert!(ImageInfo::new(&data[..data.len() - 1]).is_none());
+    }
+
+    #[test]
+    fn test_image_info_animation() {
+        use crate::{AnimEncoder, AnimFrame, WebPConfig};
+
+        let lossy = WebPConfig::new().unwrap();
+        let mut lossless = WebPConfig::new().unwrap();
+        lossless.lossless = 1;
+        let black = vec![0u8; 8 * 8 * 3];
+        let white = vec![255u8; 8 * 8 * 3];
+        let mut encoder = AnimEncoder::new(8, 8, &lossy);
+        encoder.set_loop_count(5);
+        encoder.set_bgcolor([1, 2, 3, 4]);
+        encoder.add_frame(AnimFrame::new(
+            &black,
+            PixelLayout::Rgb,
+            8,
+            8,
+            0,
+            Some(&lossless),
+        ));
+        encoder.add_frame(AnimFrame::new(&white, PixelLayout::Rgb, 8, 8, 100, None));
+        let data = encoder.encode();
+
+        let info = ImageInfo::new(&data).unwrap();
+        assert!(info.has_animation());
+        assert_eq!(info.frame_count(), 2);
+        assert_eq!(info.loop_count(), 5);
+        assert_eq!(info.bg_color(), 0x04030201);
+        assert_eq!(info.format(), CompressionFormat::Mixed);
+        // libwebp can't describe mixed animations.
+        assert!(matches!(
+            BitstreamFeatures::new(&data).unwrap().format(),
+            Some(BitstreamFormat::Undefined)
+        ));
+    }
+
     #[test]
     fn test_bitstream_features_invalid_data() {
         let data = vec![0u8; 8];
@@ -0,0 +1,84 @@
+use std::marker::PhantomData;
+
+use libwebp_sys::*;
+
+/// A safe wrapper around a libwebp demuxer which parses the container of a WebP file.
+/// The demuxer doesn't copy the data, so everything it returns borrows from the input.
+pub(crate) struct Demuxer<'a> {
+    ptr: *mut WebPDemuxer,
+    _data: PhantomData<&'a [u8]>,
+}
+
+impl<'a> Demuxer<'a> {
+    /// Parses the given data, returning `None` if it isn't a complete and valid WebP file.
+    pub(crate) fn new(data: &'a [u8]) -> Option<Self> {
+        let webp_data = WebPData {
+            bytes: data.as_ptr(),
+            size: data.len(),
+        };
+        let ptr = unsafe {
+            WebPDemuxInternal(
+                &webp_data,
+                0,
+                std::ptr::null_mut(),
+                WebPGetDemuxABIVersion(),
+            )
+        };
+        if ptr.is_null() {
+            return None;
+        }
+        Some(Self {
+            ptr,
+            _data: PhantomData,
+        })
+    }
+
+    pub(crate) fn as_ptr(&self) -> *const WebPDemuxer {
+        self.ptr
+    }
+
+    /// Returns the value of a feature of the file, like the canvas width or the frame count.
+    pub(crate) fn get_i(&self, feature: WebPFormatFeature) -> u32 {
+        unsafe { WebPDemuxGetI(self.ptr, feature) }
+    }
+
+    /// Calls `f` with the iterator of every frame, in order.
+    pub(crate) fn for_each_frame(&self, mut f: impl FnMut(&WebPIterator)) {
+        unsafe {
+            let mut iter: WebPIterator = std::mem::zeroed();
+            if WebPDemuxGetFrame(self.ptr, 1, &mut iter) == 0 {
+                return;
+            }
+            loop {
+                f(&iter);
+                if WebPDemuxNextFrame(&mut iter) == 0 {
+                    break;
+                }
+            }
+            WebPDemuxReleaseIterator(&mut iter);
+        }
+    }
+}
+
+impl Drop for Demuxer<'_> {
+    fn drop(&mut self) {
+        unsafe { WebPDemuxDelete(self.ptr) }
+    }
+}
+
+/// Returns the payload of the first chunk with the given FourCC.
+///
+/// # Safety
+/// The demuxer must be valid and the returned slice must not outlive the data it was created from.
+pub(crate) unsafe fn get_chunk<'a>(
+    demuxer: *const WebPDemuxer,
+    fourcc: &[u8; 4],
+) -> Option<&'a [u8]> {
+    let mut iter: WebPChunkIterator = std::mem::zeroed();
+    if WebPDemuxGetChunk(demuxer, fourcc.as_ptr() as *const _, 1, &mut iter) == 0 {
+        return None;
+    }
+    let chunk = std::slice::from_raw_parts(iter.chunk.bytes, iter.chunk.size);
+    WebPDemuxReleaseChunkIterator(&mut iter);
+    Some(chunk)
+}
@@ -0,0 +1,128 @@
+use libwebp_sys::{WebPEncodingError, WebPPictureDistortion};
+
+use crate::Picture;
+
+/// The metric computed by [compare].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DistortionMetric {
+    /// Peak signal-to-noise ratio.
+    Psnr,
+    /// Structural similarity.
+    Ssim,
+    /// Local similarity: the PSNR of every pixel against the closest value within 2 pixels of it
+    /// in the reference, which ignores small shifts.
+    Lsim,
+}
+
+/// The distortion between two pictures, per channel and over all channels.
+///
+/// All values are in decibels, higher values meaning more similar pictures, like `cwebp -print_psnr` prints them.
+/// Identical pictures score 99. For SSIM the value is `-10 * log10(1 - ssim)`,
+/// so an SSIM of 0.99 is 20 dB and 0.999 is 30 dB.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DistortionReport {
+    pub red: f32,
+    pub green: f32,
+    pub blue: f32,
+    pub alpha: f32,
+    pub all: f32,
+}
+
+/// Computes the distortion of `distorted` compared to `reference` with libwebp.
+///
+/// Pixel buffers in any [PixelLayout](crate::PixelLayout) can be compared by importing them with
+/// [Picture::new], and decoded images with `Picture::try_from(&image)`.
+/// Returns `VP8_ENC_ERROR_BAD_DIMENSION` if the pictures don't have the same size.
+pub fn compare(
+    reference: &Picture,
+    distorted: &Picture,
+    metric: DistortionMetric,
+) -> Result<DistortionReport, WebPEncodingError> {
+    if (reference.width(), reference.height()) != (distorted.width(), distorted.height()) {
+        return Err(WebPEncodingError::VP8_ENC_ERROR_BAD_DIMENSION);
+    }
+    let metric_type = match metric {
+        DistortionMetric::Psnr => 0,
+        DistortionMetric::Ssim => 1,
+        DistortionMetric::Lsim => 2,
+    };
+    let mut result = [0f32; 5];
+    let ok = unsafe {
+        WebPPictureDistortion(
+            distorted.as_ptr(),
+            reference.as_ptr(),
+            metric_type,
+            result.as_mut_ptr(),
+        )
+    };
+    if ok == 0 {
+        return Err(WebPEncodingError::VP8_ENC_ERROR_OUT_OF_MEMORY);
+    }
+    // libwebp reports the channels as BGRA.
+    let [blue, green, red, alpha, all] = result;
+    Ok(DistortionReport {
+        red,
+        green,
+        blue,
+        alpha,
+        all,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Decoder, Encoder, PixelLayout};
+
+    fn gradient() -> Vec<u8> {
+        (0..32 * 32)
+            .flat_map(|i| [(i % 32 * 8) as u8, (i / 32 * 8) as u8, 128])
+            .collect()
+    }
+
+    #[test]
+    fn test_compare_channels() {
+        let image = gradient();
+        let reference = Picture::new(&image, PixelLayout::Rgb, 32, 32).unwrap();
+        let report = compare(&reference, &reference, DistortionMetric::Psnr).unwrap();
+        assert_eq!(report.all, 99.0);
+
+        // Only change the red channel, to values which aren't in the reference at all.
+        let mut changed = image.clone();
+        changed.chunks_exact_mut(3).for_each(|p| p[0] = 255 - p[0]);
+        let distorted = Picture::new(&changed, PixelLayout::Rgb, 32, 32).unwrap();
+        for metric in [
+            DistortionMetric::Psnr,
+            DistortionMetric::Ssim,
+            DistortionMetric::Lsim,
+        ] {
+            let report = compare(&reference, &distorted, metric).unwrap();
+            assert!(report.red < 99.0, "{:?}: {:?}", metric, report);
+            assert_eq!(
+                (report.green, report.blue, report.alpha),
+                (99.0, 99.0, 99.0)
+            );
+            assert!(report.red < report.all && report.all < 99.0);
+        }
+
+        let smaller = Picture::new(&image, PixelLayout::Rgb, 16, 16).unwrap();
+        assert!(compare(&reference, &smaller, DistortionMetric::Psnr).is_err());
+    }
+
+    #[test]
+    fn test_compare_decoded() {
+        let image = gradient();
+        let encoder = Encoder::from_rgb(&image, 32, 32);
+        let high = Decoder::new(&encoder.encode(95.0)).decode().unwrap();
+        let low = Decoder::new(&encoder.encode(5.0)).decode().unwrap();
+        let high = encoder.compare(&high, DistortionMetric::Ssim).unwrap();
+        let low = encoder.compare(&low, DistortionMetric::Ssim).unwrap();
+        assert!(high.all > low.all, "{:?} {:?}", high, low);
+
+        let lossless = Decoder::new(&encoder.encode_lossless()).decode().unwrap();
+        let reference = Picture::new(&image, PixelLayout::Rgb, 32, 32).unwrap();
+        let lossless = Picture::try_from(&lossless).unwrap();
+        let report = compare(&reference, &lossless, DistortionMetric::Psnr).unwrap();
+        assert_eq!(report.all, 99.0);
+    }
+}
@@ -1,56 +1,131 @@
+use std::borrow::Cow;
+
 #[cfg(feature = "img")]
 use image::DynamicImage;
 use libwebp_sys::*;
 
+#[cfg(feature = "img")]
+use crate::convert::from_dynamic;
+
 use crate::shared::*;
+use crate::{compare, DistortionMetric, DistortionReport, Mux, Picture};
 use internal::CheckedEncoder;
 
 /// An encoder for WebP images. It uses the default configuration of libwebp.
 pub struct Encoder<'a> {
-    e: CheckedEncoder<'a>,
+    input: Input<'a>,
+    icc_profile: Option<&'a [u8]>,
+    exif: Option<&'a [u8]>,
+    xmp: Option<&'a [u8]>,
+    exact: bool,
+    use_sharp_yuv: bool,
 }
 
 impl<'a> Encoder<'a> {
     /// Creates a new encoder from the given image data.
     /// The image data must be in the pixel layout of the color parameter.
     pub fn new(image: &'a [u8], layout: PixelLayout, width: u32, height: u32) -> Self {
+        let stride = width as usize * usize::from(layout.bytes_per_pixel());
+        Self::with_stride(image, layout, width, height, stride)
+    }
+
+    /// Creates a new encoder from image data whose rows start `stride` bytes apart, like padded rows
+    /// or a sub-rectangle of a larger image.
+    /// The stride must be at least `width * bytes_per_pixel`, the last row needs no padding.
+    pub fn with_stride(
+        image: &'a [u8],
+        layout: PixelLayout,
+        width: u32,
+        height: u32,
+        stride: usize,
+    ) -> Self {
+        Self::from_checked(CheckedEncoder::new(
+            Cow::Borrowed(image),
+            layout,
+            width,
+            height,
+            stride,
+        ))
+    }
+
+    fn from_checked(e: CheckedEncoder<'a>) -> Self {
+        Self::from_input(Input::Pixels(e))
+    }
+
+    /// Creates a new encoder which encodes the given picture, for example after cropping or rescaling it.
+    ///
+    /// The pixels are not copied, unless lossless encoding without `exact` would change the colors
+    /// of transparent pixels, which libwebp does in place.
+    pub fn from_picture(picture: Picture<'a>) -> Self {
+        Self::from_input(Input::Picture(Box::new(picture)))
+    }
+
+    fn from_input(input: Input<'a>) -> Self {
         Self {
-            e: CheckedEncoder::new(image, layout, width, height),
+            input,
+            icc_profile: None,
+            exif: None,
+            xmp: None,
+            exact: false,
+            use_sharp_yuv: false,
         }
     }
 
     #[cfg(feature = "img")]
     /// Creates a new encoder from the given image.
+    ///
+    /// RGB8 and RGBA8 images are borrowed, every other variant is converted to them first and owned by the encoder.
+    /// Gray is expanded to RGB, 16-bit samples are rounded to the nearest 8-bit value without dithering,
+    /// and floating point samples are clamped to [0, 1] before rounding.
+    /// Returns an error for variants added to the image crate in the future.
     pub fn from_image(image: &'a DynamicImage) -> Result<Self, &'a str> {
-        match image {
-            DynamicImage::ImageLuma8(_) => Err("Unimplemented"),
-            DynamicImage::ImageLumaA8(_) => Err("Unimplemented"),
-            DynamicImage::ImageRgb8(image) => Ok(Self::from_rgb(
-                image.as_ref(),
-                image.width(),
-                image.height(),
-            )),
-            DynamicImage::ImageRgba8(image) => Ok(Self::from_rgba(
-                image.as_ref(),
-                image.width(),
-                image.height(),
-            )),
-            _ => Err("Unimplemented"),
-        }
+        let (image_data, layout) = from_dynamic(image).ok_or("Unimplemented")?;
+        let stride = image.width() as usize * usize::from(layout.bytes_per_pixel());
+        Ok(Self::from_checked(CheckedEncoder::new(
+            image_data,
+            layout,
+            image.width(),
+            image.height(),
+            stride,
+        )))
     }
 
     /// Creates a new encoder from the given image data in the RGB pixel layout.
     pub fn from_rgb(image: &'a [u8], width: u32, height: u32) -> Self {
-        Self {
-            e: CheckedEncoder::new(image, PixelLayout::Rgb, width, height),
-        }
+        Self::new(image, PixelLayout::Rgb, width, height)
     }
 
     /// Creates a new encoder from the given image data in the RGBA pixel layout.
+    /// If every pixel is opaque, the encoded image has no alpha channel.
     pub fn from_rgba(image: &'a [u8], width: u32, height: u32) -> Self {
-        Self {
-            e: CheckedEncoder::new(image, PixelLayout::Rgba, width, height),
-        }
+        Self::new(image, PixelLayout::Rgba, width, height)
+    }
+
+    /// Embeds the given ICC color profile in the encoded image.
+    pub fn set_icc_profile(&mut self, icc_profile: &'a [u8]) {
+        self.icc_profile = Some(icc_profile);
+    }
+
+    /// Embeds the given EXIF metadata in the encoded image.
+    pub fn set_exif(&mut self, exif: &'a [u8]) {
+        self.exif = Some(exif);
+    }
+
+    /// Embeds the given XMP metadata in the encoded image.
+    pub fn set_xmp(&mut self, xmp: &'a [u8]) {
+        self.xmp = Some(xmp);
+    }
+
+    /// Keeps the RGB values under transparent pixels instead of changing them to compress better.
+    /// Applies to the simple encoding methods, [encode_advanced](Encoder::encode_advanced) uses `exact` of its configuration.
+    pub fn set_exact(&mut self, exact: bool) {
+        self.exact = exact;
+    }
+
+    /// Uses the slower but sharper RGB to YUV conversion for lossy encoding.
+    /// Applies to the simple encoding methods, [encode_advanced](Encoder::encode_advanced) uses `use_sharp_yuv` of its configuration.
+    pub fn set_use_sharp_yuv(&mut self, use_sharp_yuv: bool) {
+        self.use_sharp_yuv = use_sharp_yuv;
     }
 
     /// Encode the image with the given quality.
@@ -64,53 +139,150 @@ impl<'a> Encoder<'a> {
         self.encode_simple(true, 75.0).unwrap()
     }
 
+    /// Encode the image losslessly after adjusting pixel values to compress better.
+    /// The level is between 0 for the strongest preprocessing and 100 for none, which is the same as
+    /// [encode_lossless](Encoder::encode_lossless).
+    pub fn encode_near_lossless(&self, level: u8) -> Result<WebPMemory, WebPEncodingError> {
+        let mut config = self.simple_config(true, 75.0);
+        config.near_lossless = i32::from(level.min(100));
+        self.encode_advanced(&config)
+    }
+
     pub fn encode_simple(
         &self,
         lossless: bool,
         quality: f32,
     ) -> Result<WebPMemory, WebPEncodingError> {
+        self.encode_advanced(&self.simple_config(lossless, quality))
+    }
+
+    fn simple_config(&self, lossless: bool, quality: f32) -> WebPConfig {
         let mut config = WebPConfig::new().unwrap();
         config.lossless = if lossless { 1 } else { 0 };
         config.alpha_compression = if lossless { 0 } else { 1 };
         config.quality = quality;
-        self.encode_advanced(&config)
+        config.exact = if self.exact { 1 } else { 0 };
+        config.use_sharp_yuv = if self.use_sharp_yuv { 1 } else { 0 };
+        config
     }
 
+    /// Encode the image with the given configuration.
+    ///
+    /// If any metadata was set, the result is an extended (VP8X) file containing it.
+    /// Failures while adding the metadata are reported as `VP8_ENC_ERROR_OUT_OF_MEMORY` or `VP8_ENC_ERROR_BAD_WRITE`.
     pub fn encode_advanced(&self, config: &WebPConfig) -> Result<WebPMemory, WebPEncodingError> {
-        unsafe {
-            let mut picture = new_picture(
-                self.e.image(),
-                self.e.layout(),
-                self.e.width(),
-                self.e.height(),
-            );
-            let res = encode(&mut *picture, config);
-            res
+        self.encode_with_stats(config, None)
+    }
+
+    /// Like [encode_advanced](Encoder::encode_advanced), additionally filling in the statistics of libwebp if given.
+    pub(crate) fn encode_with_stats(
+        &self,
+        config: &WebPConfig,
+        stats: Option<&mut WebPAuxStats>,
+    ) -> Result<WebPMemory, WebPEncodingError> {
+        let mut picture = match &self.input {
+            Input::Pixels(e) => unsafe {
+                new_picture(e.image(), e.layout(), e.width(), e.height(), e.stride())
+            },
+            Input::Picture(picture) => picture.for_encoding(config)?,
+        };
+        if let Some(stats) = stats {
+            picture.stats = stats;
+        }
+        let mem = unsafe { encode(&mut picture, config)? };
+        if self.icc_profile.is_none() && self.exif.is_none() && self.xmp.is_none() {
+            return Ok(mem);
         }
+        self.add_metadata(&mem)
+    }
+
+    /// Copies the input of the encoder into a new [Picture].
+    pub fn to_picture(&self) -> Result<Picture<'static>, WebPEncodingError> {
+        match &self.input {
+            Input::Pixels(e) => {
+                Picture::with_stride(e.image(), e.layout(), e.width(), e.height(), e.stride())
+            }
+            Input::Picture(picture) => picture
+                .view(0, 0, picture.width(), picture.height())?
+                .into_owned(),
+        }
+    }
+
+    /// Computes the distortion of a decoded image compared to the input of the encoder, see [compare].
+    pub fn compare(
+        &self,
+        decoded: &WebPImage,
+        metric: DistortionMetric,
+    ) -> Result<DistortionReport, WebPEncodingError> {
+        compare(&self.to_picture()?, &Picture::try_from(decoded)?, metric)
+    }
+
+    fn add_metadata(&self, bitstream: &[u8]) -> Result<WebPMemory, WebPEncodingError> {
+        Mux::from_data(bitstream)
+            .and_then(|mut mux| {
+                mux.set_metadata_chunks(self.icc_profile, self.exif, self.xmp)?;
+                mux.assemble()
+            })
+            .map_err(|mux_error| match mux_error {
+                WebPMuxError::WEBP_MUX_MEMORY_ERROR => {
+                    WebPEncodingError::VP8_ENC_ERROR_OUT_OF_MEMORY
+                }
+                _ => WebPEncodingError::VP8_ENC_ERROR_BAD_WRITE,
+            })
+    }
+}
+
+impl Encoder<'static> {
+    /// Creates a new encoder which takes ownership of the given image data.
+    /// The image data must be in the pixel layout of the layout parameter.
+    pub fn from_vec(image: Vec<u8>, layout: PixelLayout, width: u32, height: u32) -> Self {
+        let stride = width as usize * usize::from(layout.bytes_per_pixel());
+        Self::from_checked(CheckedEncoder::new(
+            Cow::Owned(image),
+            layout,
+            width,
+            height,
+            stride,
+        ))
     }
 }
 
+/// The image an [Encoder] encodes.
+enum Input<'a> {
+    Pixels(CheckedEncoder<'a>),
+    Picture(Box<Picture<'a>>),
+}
+
 /// This module contains the private CheckedEncoder so that it cannot be constructed directly from the outside.
 /// That ensures that the only way to construct one validates the supplied parameters.
 mod internal {
+    use std::borrow::Cow;
     use std::convert::TryInto;
 
-    use crate::shared::PixelLayout;
+    use crate::shared::{min_buffer_len, PixelLayout};
 
-    /// Validated encoder parameters, guaranteeing `image.len() >= width * height * bytes_per_pixel`.
+    /// Validated encoder parameters, guaranteeing `stride >= width * bytes_per_pixel` and
+    /// `image.len() >= stride * (height - 1) + width * bytes_per_pixel`.
     /// Required for memory safety. Their absence would allow out-of-bounds reads.
     pub(super) struct CheckedEncoder<'a> {
-        image: &'a [u8],
+        image: Cow<'a, [u8]>,
         layout: PixelLayout,
         width: u32,
         height: u32,
+        stride: usize,
     }
 
     impl<'a> CheckedEncoder<'a> {
         /// Creates a new instance of `CheckedEncoder`, and performs the necessary bounds checks.
         ///
         /// This is the only way to create a `CheckedEncoder` exposed outside the `internal` module.
-        pub(super) fn new(image: &'a [u8], layout: PixelLayout, width: u32, height: u32) -> Self {
+        pub(super) fn new(
+            image: Cow<'a, [u8]>,
+            layout: PixelLayout,
+            width: u32,
+            height: u32,
+            stride: usize,
+        ) -> Self {
             // TODO: return an error instead of panicking in the next semver-breaking release
 
             if width == 0 || height == 0 {
@@ -122,26 +294,36 @@ mod internal {
                 //panic!("Width and height must be non-zero.");
             }
 
-            // We're going to compare the incoming width * height * bpp against the length of a slice.
+            // We're going to compare the incoming dimensions and stride against the length of a slice.
             // The length of a slice is a `usize`, so we are going to do arithmetic in `usize` as well.
             //
             // On 32-bit and 64-bit platforms these conversions always suceeed and get optimized out.
             let width_u: usize = width.try_into().unwrap();
-            let height_u: usize = height.try_into().unwrap();
             let bytes_per_pixel_u: usize = layout.bytes_per_pixel().into();
 
+            // Rows must not overlap, or libwebp would read pixels of the next row.
+            let row_len = width_u.saturating_mul(bytes_per_pixel_u);
+            if stride < row_len {
+                panic!(
+                    "Stride too small. Expected at least {} bytes for a row of {} pixels with {:?} layout, got {}.",
+                    row_len, width, layout, stride
+                );
+            }
+            // libwebp takes the stride as an `int`, a wrapped around value would make it read out of bounds.
+            if i32::try_from(stride).is_err() {
+                panic!("Stride too large, got {}.", stride);
+            }
+
             // If we simply calculate `width * height * bytes_per_pixel`, arithmetic may overflow in release mode
-            // and make it possible to bypass the length check. So we need either .checked_mul or .saturating_mul here.
+            // and make it possible to bypass the length check. So `min_buffer_len` uses saturating arithmetic.
             //
-            // Using `saturating_mul` is enough because it's not possible to construct a valid slice of size `usize::MAX` anyway,
+            // That is enough because it's not possible to construct a valid slice of size `usize::MAX` anyway,
             // and it makes for simpler code and a nicer error message.
-            let expected_len = width_u
-                .saturating_mul(height_u)
-                .saturating_mul(bytes_per_pixel_u);
+            let expected_len = min_buffer_len(layout, width, height, stride);
             if image.len() < expected_len {
                 panic!(
-                    "Image buffer too small. Expected at least {} bytes for a {}x{} image with {:?} layout, got {}.",
-                    expected_len, width, height, layout, image.len()
+                    "Image buffer too small. Expected at least {} bytes for a {}x{} image with {:?} layout and a stride of {}, got {}.",
+                    expected_len, width, height, layout, stride, image.len()
                 );
             }
 
@@ -154,6 +336,7 @@ mod internal {
                 layout,
                 width,
                 height,
+                stride,
             }
         }
 
@@ -169,8 +352,12 @@ mod internal {
             self.layout
         }
 
-        pub(super) fn image(&self) -> &'a [u8] {
-            self.image
+        pub(super) fn image(&self) -> &[u8] {
+            &self.image
+        }
+
+        pub(super) fn stride(&self) -> usize {
+            self.stride
         }
     }
 }
@@ -180,6 +367,7 @@ pub(crate) unsafe fn new_picture(
     layout: PixelLayout,
     width: u32,
     height: u32,
+    stride: usize,
 ) -> ManageedPicture {
     let mut picture = WebPPicture::new().unwrap();
     picture.use_argb = 1;
@@ -187,10 +375,10 @@ pub(crate) unsafe fn new_picture(
     picture.height = height as i32;
     match layout {
         PixelLayout::Rgba => {
-            WebPPictureImportRGBA(&mut picture, image.as_ptr(), width as i32 * 4);
+            WebPPictureImportRGBA(&mut picture, image.as_ptr(), stride as i32);
         }
         PixelLayout::Rgb => {
-            WebPPictureImportRGB(&mut picture, image.as_ptr(), width as i32 * 3);
+            WebPPictureImportRGB(&mut picture, image.as_ptr(), stride as i32);
         }
     }
     ManageedPicture(picture)
@@ -221,6 +409,13 @@ mod tests {
     use super::*;
     use crate::shared;
 
+    fn pixels<'a, 'b>(encoder: &'b Encoder<'a>) -> &'b CheckedEncoder<'a> {
+        match &encoder.input {
+            Input::Pixels(e) => e,
+            Input::Picture(_) => panic!("The encoder has a picture as input"),
+        }
+    }
+
     #[test]
     #[should_panic]
     fn construct_encoder_with_buffer_overflow() {
@@ -231,10 +426,10 @@ mod tests {
     fn test_encoder_new_assigns_fields() {
         let data = [5; 18];
         let enc = Encoder::new(&data, shared::PixelLayout::Rgb, 2, 3);
-        assert_eq!(enc.e.image(), &data);
-        assert_eq!(enc.e.layout(), shared::PixelLayout::Rgb);
-        assert_eq!(enc.e.width(), 2);
-        assert_eq!(enc.e.height(), 3);
+        assert_eq!(pixels(&enc).image(), &data);
+        assert_eq!(pixels(&enc).layout(), shared::PixelLayout::Rgb);
+        assert_eq!(pixels(&enc).width(), 2);
+        assert_eq!(pixels(&enc).height(), 3);
     }
 
     #[test]
@@ -243,23 +438,47 @@ mod tests {
         let rgba = [1, 2, 3, 4, 5, 6, 7, 8];
         let enc_rgb = Encoder::from_rgb(&rgb, 2, 1);
         let enc_rgba = Encoder::from_rgba(&rgba, 2, 1);
-        assert_eq!(enc_rgb.e.layout(), shared::PixelLayout::Rgb);
-        assert_eq!(enc_rgba.e.layout(), shared::PixelLayout::Rgba);
-        assert_eq!(enc_rgb.e.image(), &rgb);
-        assert_eq!(enc_rgba.e.image(), &rgba);
-        assert_eq!(enc_rgb.e.width(), 2);
-        assert_eq!(enc_rgba.e.height(), 1);
+        assert_eq!(pixels(&enc_rgb).layout(), shared::PixelLayout::Rgb);
+        assert_eq!(pixels(&enc_rgba).layout(), shared::PixelLayout::Rgba);
+        assert_eq!(pixels(&enc_rgb).image(), &rgb);
+        assert_eq!(pixels(&enc_rgba).image(), &rgba);
+        assert_eq!(pixels(&enc_rgb).width(), 2);
+        assert_eq!(pixels(&enc_rgba).height(), 1);
     }
 
     #[cfg(feature = "img")]
     #[test]
-    fn test_encoder_from_image_error_branches() {
+    fn test_encoder_from_image_variants() {
         use image::{DynamicImage, ImageBuffer};
 
-        let luma = DynamicImage::ImageLuma8(ImageBuffer::from_pixel(1, 1, image::Luma([0])));
-        let luma_a = DynamicImage::ImageLumaA8(ImageBuffer::from_pixel(1, 1, image::LumaA([0, 0])));
-        assert!(Encoder::from_image(&luma).is_err());
-        assert!(Encoder::from_image(&luma_a).is_err());
+        let luma = DynamicImage::ImageLuma8(ImageBuffer::from_pixel(1, 1, image::Luma([7])));
+        let enc = Encoder::from_image(&luma).unwrap();
+        assert_eq!(pixels(&enc).layout(), shared::PixelLayout::Rgb);
+        assert_eq!(pixels(&enc).image(), &[7, 7, 7]);
+        let luma_a = DynamicImage::ImageLumaA8(ImageBuffer::from_pixel(1, 1, image::LumaA([7, 9])));
+        let enc = Encoder::from_image(&luma_a).unwrap();
+        assert_eq!(pixels(&enc).image(), &[7, 7, 7, 9]);
+
+        let rgba16 = DynamicImage::ImageRgba16(ImageBuffer::from_pixel(
+            1,
+            1,
+            image::Rgba([0, 128, 129, u16::MAX]),
+        ));
+        assert_eq!(
+            pixels(&Encoder::from_image(&rgba16).unwrap()).image(),
+            &[0, 0, 1, 255]
+        );
+        let la16 =
+            DynamicImage::ImageLumaA16(ImageBuffer::from_pixel(1, 1, image::LumaA([514, 0])));
+        assert_eq!(
+            pixels(&Encoder::from_image(&la16).unwrap()).image(),
+            &[2, 2, 2, 0]
+        );
+        let rgb32f =
+            DynamicImage::ImageRgb32F(ImageBuffer::from_pixel(1, 1, image::Rgb([-0.5, 0.5, 2.0])));
+        let enc = Encoder::from_image(&rgb32f).unwrap();
+        assert_eq!(pixels(&enc).image(), &[0, 128, 255]);
+        assert!(!enc.encode_lossless().is_empty());
 
         let rgb = DynamicImage::ImageRgb8(ImageBuffer::from_pixel(2, 2, image::Rgb([1, 2, 3])));
         let rgba =
@@ -268,6 +487,129 @@ mod tests {
         assert!(Encoder::from_image(&rgba).is_ok());
     }
 
+    #[test]
+    fn test_encoder_from_vec() {
+        let encoder = {
+            let image = vec![1u8, 2, 3, 4, 5, 6];
+            Encoder::from_vec(image, PixelLayout::Rgb, 2, 1)
+        };
+        assert_eq!(pixels(&encoder).image(), &[1, 2, 3, 4, 5, 6]);
+        let data = encoder.encode_lossless();
+        let decoded = crate::Decoder::new(&data).decode().unwrap();
+        assert_eq!(&*decoded, &[1, 2, 3, 4, 5, 6]);
+    }
+
+    #[test]
+    fn test_encoder_with_stride() {
+        // A 2x2 RGB image with 2 bytes of padding per row, the last row unpadded.
+        let padded = [1, 2, 3, 4, 5, 6, 0, 0, 7, 8, 9, 10, 11, 12];
+        let packed = [1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12];
+        let data = Encoder::with_stride(&padded, PixelLayout::Rgb, 2, 2, 8).encode_lossless();
+        let decoded = crate::Decoder::new(&data).decode().unwrap();
+        assert_eq!(&*decoded, &packed);
+
+        // The 1x2 sub-rectangle at (1, 0) of a 3x2 RGBA image.
+        let image: Vec<u8> = (0..3 * 2 * 4).collect();
+        let data = Encoder::with_stride(&image[4..], PixelLayout::Rgba, 1, 2, 12).encode_lossless();
+        let decoded = crate::Decoder::new(&data).decode().unwrap();
+        assert_eq!(&*decoded, &[4, 5, 6, 7, 16, 17, 18, 19]);
+    }
+
+    #[test]
+    #[should_panic]
+    fn construct_encoder_with_short_stride() {
+        Encoder::with_stride(&[0; 12], PixelLayout::Rgb, 2, 2, 5);
+    }
+
+    #[test]
+    fn test_encode_exact() {
+        // Transparent pixels with different colors under them, and an opaque one.
+        let image = [
+            10, 20, 30, 0, 200, 100, 50, 0, 1, 2, 3, 0, 255, 255, 255, 255,
+        ];
+        let mut encoder = Encoder::from_rgba(&image, 2, 2);
+        let decode = |data: &[u8]| crate::Decoder::new(data).decode().unwrap().to_vec();
+        assert_ne!(decode(&encoder.encode_lossless()), image);
+
+        encoder.set_exact(true);
+        assert_eq!(decode(&encoder.encode_lossless()), image);
+        assert_eq!(decode(&encoder.encode_near_lossless(100).unwrap()), image);
+        let lossy = decode(&encoder.encode(100.0));
+        assert_eq!(
+            lossy.iter().skip(3).step_by(4).collect::<Vec<_>>(),
+            [&0, &0, &0, &255]
+        );
+    }
+
+    #[test]
+    fn test_encode_near_lossless_and_sharp_yuv() {
+        let image: Vec<u8> = (0..16 * 16 * 3).map(|i| (i * 37 % 251) as u8).collect();
+        let mut encoder = Encoder::from_rgb(&image, 16, 16);
+        assert_eq!(
+            &*encoder.encode_near_lossless(100).unwrap(),
+            &*encoder.encode_lossless()
+        );
+        let near = encoder.encode_near_lossless(0).unwrap();
+        let info = crate::Decoder::new(&near).info().unwrap();
+        assert_eq!(info.format(), crate::CompressionFormat::Lossless);
+        let decoded = crate::Decoder::new(&near).decode().unwrap();
+        assert_ne!(&*decoded, &image[..]);
+
+        let plain = encoder.encode(75.0);
+        encoder.set_use_sharp_yuv(true);
+        let sharp = encoder.encode(75.0);
+        assert_ne!(&*plain, &*sharp);
+        assert!(crate::Decoder::new(&sharp).decode().is_some());
+    }
+
+    #[test]
+    fn test_encode_opaque_rgba() {
+        // libwebp leaves out the alpha channel if every pixel is opaque.
+        let rgb: Vec<u8> = (0..16 * 16 * 3).map(|i| (i * 37 % 251) as u8).collect();
+        let rgba: Vec<u8> = rgb
+            .chunks_exact(3)
+            .flat_map(|p| [p[0], p[1], p[2], 255])
+            .collect();
+        for lossless in [false, true] {
+            let from_rgb = Encoder::from_rgb(&rgb, 16, 16)
+                .encode_simple(lossless, 75.0)
+                .unwrap();
+            let from_rgba = Encoder::from_rgba(&rgba, 16, 16)
+                .encode_simple(lossless, 75.0)
+                .unwrap();
+            assert_eq!(&*from_rgb, &*from_rgba);
+            assert!(!crate::BitstreamFeatures::new(&from_rgba)
+                .unwrap()
+                .has_alpha());
+        }
+    }
+
+    #[test]
+    fn test_encode_with_metadata() {
+        let image = [200u8; 4 * 4 * 3];
+        let icc = b"fake icc profile";
+        let exif = b"Exif\0\0fake";
+        let xmp = b"<x:xmpmeta/>";
+        let mut encoder = Encoder::from_rgb(&image, 4, 4);
+        encoder.set_icc_profile(icc);
+        encoder.set_exif(exif);
+        encoder.set_xmp(xmp);
+        let mem = encoder.encode(75.0);
+
+        assert_eq!(&mem[12..16], b"VP8X");
+        let flags = mem[20] as u32;
+        assert_ne!(flags & WebPFeatureFlags::ICCP_FLAG as u32, 0);
+        assert_ne!(flags & WebPFeatureFlags::EXIF_FLAG as u32, 0);
+        assert_ne!(flags & WebPFeatureFlags::XMP_FLAG as u32, 0);
+        for payload in [&icc[..], &exif[..], &xmp[..]] {
+            assert!(mem.windows(payload.len()).any(|w| w == payload));
+        }
+        assert!(crate::Decoder::new(&mem).decode().is_some());
+
+        let plain = Encoder::from_rgb(&image, 4, 4).encode(75.0);
+        assert_eq!(&plain[12..16], b"VP8 ");
+    }
+
     #[test]
     fn test_encode_runs_without_panic() {
         let width = 2;
@@ -0,0 +1,219 @@
+use std::borrow::Cow;
+use std::io::Read;
+
+use image::error::{DecodingError, ImageFormatHint};
+use image::{
+    AnimationDecoder, ColorType, Delay, DynamicImage, Frame, Frames, ImageDecoder, ImageError,
+    ImageFormat, ImageResult, RgbaImage,
+};
+use libwebp_sys::{WebPDecodeRGBAInto, WebPDecodeRGBInto};
+
+use crate::{AnimDecoder, ImageInfo, Metadata};
+
+/// An [ImageDecoder] and [AnimationDecoder] for the image crate which decodes with libwebp.
+///
+/// Still images are decoded to RGB8, or RGBA8 if they have an alpha channel.
+/// Animations are always decoded to RGBA8, and as an [ImageDecoder] only their first frame is read.
+pub struct WebPDecoder<'a> {
+    data: Cow<'a, [u8]>,
+    info: ImageInfo,
+}
+
+fn decoding_error(message: &str) -> ImageError {
+    ImageError::Decoding(DecodingError::new(
+        ImageFormatHint::Exact(ImageFormat::WebP),
+        message,
+    ))
+}
+
+impl<'a> WebPDecoder<'a> {
+    /// Creates a decoder for the given WebP file, reading only its container.
+    pub fn new(data: &'a [u8]) -> ImageResult<Self> {
+        Self::from_cow(Cow::Borrowed(data))
+    }
+
+    fn from_cow(data: Cow<'a, [u8]>) -> ImageResult<Self> {
+        let info = ImageInfo::new(&data).ok_or_else(|| decoding_error("Invalid WebP file"))?;
+        Ok(Self { data, info })
+    }
+
+    /// Returns the information read from the container.
+    pub fn info(&self) -> &ImageInfo {
+        &self.info
+    }
+
+    /// Reads the ICC profile, EXIF and XMP metadata of the image.
+    ///
+    /// The ICC profile is also returned by [ImageDecoder::icc_profile].
+    pub fn metadata(&self) -> Metadata {
+        Metadata::new(&self.data).unwrap_or_default()
+    }
+}
+
+impl WebPDecoder<'static> {
+    /// Reads a whole WebP file from the reader and creates a decoder for it.
+    pub fn from_reader(mut reader: impl Read) -> ImageResult<Self> {
+        let mut data = Vec::new();
+        reader.read_to_end(&mut data)?;
+        Self::from_cow(Cow::Owned(data))
+    }
+}
+
+impl ImageDecoder for WebPDecoder<'_> {
+    fn dimensions(&self) -> (u32, u32) {
+        (self.info.width(), self.info.height())
+    }
+
+    fn color_type(&self) -> ColorType {
+        if self.info.has_alpha() || self.info.has_animation() {
+            ColorType::Rgba8
+        } else {
+            ColorType::Rgb8
+        }
+    }
+
+    fn icc_profile(&mut self) -> ImageResult<Option<Vec<u8>>> {
+        Ok(self.metadata().icc_profile)
+    }
+
+    fn read_image(self, buf: &mut [u8]) -> ImageResult<()> {
+        assert_eq!(u64::try_from(buf.len()), Ok(self.total_bytes()));
+
+        if self.info.has_animation() {
+            let anim = AnimDecoder::new(&self.data)
+                .decode()
+                .map_err(|error| decoding_error(&error))?;
+            let frame = anim
+                .get_frame(0)
+                .ok_or_else(|| decoding_error("Animation without frames"))?;
+            buf.copy_from_slice(frame.get_image());
+            return Ok(());
+        }
+
+        let stride = self.info.width() as i32 * i32::from(self.color_type().bytes_per_pixel());
+        let result = unsafe {
+            if self.info.has_alpha() {
+                WebPDecodeRGBAInto(
+                    self.data.as_ptr(),
+                    self.data.len(),
+                    buf.as_mut_ptr(),
+                    buf.len(),
+                    stride,
+                )
+            } else {
+                WebPDecodeRGBInto(
+                    self.data.as_ptr(),
+                    self.data.len(),
+                    buf.as_mut_ptr(),
+                    buf.len(),
+                    stride,
+                )
+            }
+        };
+        if result.is_null() {
+            return Err(decoding_error("Failed to decode the image"));
+        }
+        Ok(())
+    }
+
+    fn read_image_boxed(self: Box<Self>, buf: &mut [u8]) -> ImageResult<()> {
+        (*self).read_image(buf)
+    }
+}
+
+impl<'a> AnimationDecoder<'a> for WebPDecoder<'a> {
+    /// Decodes all frames at once, still images are returned as a single frame.
+    fn into_frames(self) -> Frames<'a> {
+        let (width, height) = self.dimensions();
+        let frames = if self.info.has_animation() {
+            AnimDecoder::new(&self.data)
+                .decode()
+                .map_err(|error| decoding_error(&error))
+                .map(|anim| {
+                    // libwebp reports the time at which each frame ends.
+                    let mut end_of_previous = 0;
+                    anim.into_frames()
+                        .into_iter()
+                        .map(|frame| {
+                            let duration = frame.get_time_ms() - end_of_previous;
+                            end_of_previous = frame.get_time_ms();
+                            let delay = Delay::from_numer_denom_ms(duration.max(0) as u32, 1);
+                            let buffer = RgbaImage::from_raw(width, height, frame.into_vec())
+                                .expect("The frame has the size of the canvas");
+                            Frame::from_parts(buffer, 0, 0, delay)
+                        })
+                        .collect::<Vec<_>>()
+                })
+        } else {
+            DynamicImage::from_decoder(self).map(|image| vec![Frame::new(image.into_rgba8())])
+        };
+        match frames {
+            Ok(frames) => Frames::new(Box::new(frames.into_iter().map(Ok))),
+            Err(error) => Frames::new(Box::new(std::iter::once(Err(error)))),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use image::RgbImage;
+
+    use super::*;
+    use crate::{AnimEncoder, AnimFrame, Encoder, WebPConfig};
+
+    #[test]
+    fn test_image_decoder() {
+        let image: Vec<u8> = (0..6 * 4 * 4).map(|i| (i * 7 % 256) as u8 | 1).collect();
+        let mut encoder = Encoder::from_rgba(&image, 6, 4);
+        encoder.set_icc_profile(b"icc");
+        encoder.set_exif(b"exif");
+        let data = encoder.encode_lossless();
+
+        let mut decoder = WebPDecoder::new(&data).unwrap();
+        assert_eq!(decoder.dimensions(), (6, 4));
+        assert_eq!(decoder.color_type(), ColorType::Rgba8);
+        assert_eq!(decoder.icc_profile().unwrap(), Some(b"icc".to_vec()));
+        assert_eq!(decoder.metadata().exif, Some(b"exif".to_vec()));
+        let decoded = DynamicImage::from_decoder(decoder).unwrap();
+        assert_eq!(decoded.as_bytes(), &image[..]);
+
+        let rgb = RgbImage::from_pixel(3, 3, [10, 20, 30].into());
+        let data = Encoder::from_rgb(rgb.as_raw(), 3, 3).encode_lossless();
+        let decoder = WebPDecoder::from_reader(&data[..]).unwrap();
+        assert_eq!(decoder.color_type(), ColorType::Rgb8);
+        let decoded = DynamicImage::from_decoder(decoder).unwrap();
+        assert_eq!(decoded.to_rgb8(), rgb);
+        let frames = WebPDecoder::new(&data).unwrap().into_frames();
+        assert_eq!(frames.collect_frames().unwrap().len(), 1);
+
+        assert!(WebPDecoder::new(&data[..data.len() - 1]).is_err());
+    }
+
+    #[test]
+    fn test_animation_decoder() {
+        let mut config = WebPConfig::new().unwrap();
+        config.lossless = 1;
+        let mut encoder = AnimEncoder::new(4, 4, &config);
+        encoder.set_loop_count(2);
+        for (i, value) in [0u8, 100, 200].into_iter().enumerate() {
+            let frame = vec![value; 4 * 4 * 4];
+            encoder.add_frame(AnimFrame::from_rgba(&frame, 4, 4, i as i32 * 50).into_owned());
+        }
+        let data = encoder.encode();
+
+        let decoder = WebPDecoder::new(&data).unwrap();
+        assert_eq!(decoder.color_type(), ColorType::Rgba8);
+        assert_eq!(decoder.info().loop_count(), 2);
+        let first = DynamicImage::from_decoder(WebPDecoder::new(&data).unwrap()).unwrap();
+        assert_eq!(first.as_bytes(), &[0; 4 * 4 * 4][..]);
+
+        let frames = decoder.into_frames().collect_frames().unwrap();
+        assert_eq!(frames.len(), 3);
+        assert_eq!(frames[1].buffer().as_raw(), &vec![100; 4 * 4 * 4]);
+        assert_eq!(
+            frames[0].delay(),
+            Delay::from_numer_denom_ms(50, 1),
+            "the first frame lasts until the second one starts"
+        );
+    }
+}
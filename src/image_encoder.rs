@@ -0,0 +1,169 @@
+use std::io::Write;
+
+use image::error::{EncodingError, ImageFormatHint, UnsupportedError, UnsupportedErrorKind};
+use image::{ExtendedColorType, ImageEncoder, ImageError, ImageFormat, ImageResult};
+use libwebp_sys::WebPConfig;
+
+use crate::convert::from_extended;
+use crate::Encoder;
+
+/// An [ImageEncoder] for the image crate which encodes with libwebp and writes the result to `W`.
+///
+/// Every 8-bit, 16-bit and floating point gray, RGB and BGR color type is accepted.
+/// Other types than RGB8 and RGBA8 are converted first: gray is expanded to RGB,
+/// 16-bit samples are rounded to the nearest 8-bit value and floating point samples are clamped to [0, 1].
+pub struct WebPEncoder<W: Write> {
+    writer: W,
+    config: WebPConfig,
+    icc_profile: Option<Vec<u8>>,
+    exif: Option<Vec<u8>>,
+    xmp: Option<Vec<u8>>,
+}
+
+impl<W: Write> WebPEncoder<W> {
+    /// Creates an encoder which writes lossy images with libwebp's default quality of 75.
+    pub fn new(writer: W) -> Self {
+        Self::with_config(writer, &WebPConfig::new().unwrap())
+    }
+
+    /// Creates an encoder which writes lossy images with the given quality between 0 and 100.
+    pub fn new_with_quality(writer: W, quality: f32) -> Self {
+        let mut config = WebPConfig::new().unwrap();
+        config.quality = quality;
+        Self::with_config(writer, &config)
+    }
+
+    /// Creates an encoder which writes lossless images.
+    pub fn new_lossless(writer: W) -> Self {
+        let mut config = WebPConfig::new().unwrap();
+        config.lossless = 1;
+        Self::with_config(writer, &config)
+    }
+
+    /// Creates an encoder which uses the given configuration.
+    pub fn with_config(writer: W, config: &WebPConfig) -> Self {
+        Self {
+            writer,
+            config: *config,
+            icc_profile: None,
+            exif: None,
+            xmp: None,
+        }
+    }
+
+    /// Embeds the given ICC profile in the encoded image.
+    pub fn set_icc_profile(&mut self, icc_profile: Vec<u8>) {
+        self.icc_profile = Some(icc_profile);
+    }
+
+    /// Embeds the given EXIF metadata in the encoded image.
+    pub fn set_exif(&mut self, exif: Vec<u8>) {
+        self.exif = Some(exif);
+    }
+
+    /// Embeds the given XMP metadata in the encoded image.
+    pub fn set_xmp(&mut self, xmp: Vec<u8>) {
+        self.xmp = Some(xmp);
+    }
+}
+
+impl<W: Write> ImageEncoder for WebPEncoder<W> {
+    fn write_image(
+        mut self,
+        buf: &[u8],
+        width: u32,
+        height: u32,
+        color_type: ExtendedColorType,
+    ) -> ImageResult<()> {
+        let expected_len =
+            u64::from(width) * u64::from(height) * u64::from(color_type.bits_per_pixel()) / 8;
+        assert_eq!(expected_len, buf.len() as u64, "Invalid buffer length");
+
+        let (image, layout) = from_extended(buf, color_type).ok_or_else(|| {
+            ImageError::Unsupported(UnsupportedError::from_format_and_kind(
+                ImageFormat::WebP.into(),
+                UnsupportedErrorKind::Color(color_type),
+            ))
+        })?;
+        let mut encoder = Encoder::new(&image, layout, width, height);
+        if let Some(icc_profile) = &self.icc_profile {
+            encoder.set_icc_profile(icc_profile);
+        }
+        if let Some(exif) = &self.exif {
+            encoder.set_exif(exif);
+        }
+        if let Some(xmp) = &self.xmp {
+            encoder.set_xmp(xmp);
+        }
+        let data = encoder.encode_advanced(&self.config).map_err(|error| {
+            ImageError::Encoding(EncodingError::new(
+                ImageFormatHint::Exact(ImageFormat::WebP),
+                format!("{:?}", error),
+            ))
+        })?;
+        self.writer.write_all(&data)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use image::{DynamicImage, Rgb32FImage, RgbaImage};
+
+    use super::*;
+    use crate::{Decoder, PixelLayout};
+
+    fn encode(buf: &[u8], width: u32, height: u32, color_type: ExtendedColorType) -> Vec<u8> {
+        let mut data = Vec::new();
+        WebPEncoder::new_lossless(&mut data)
+            .write_image(buf, width, height, color_type)
+            .unwrap();
+        data
+    }
+
+    #[test]
+    fn test_image_encoder_color_types() {
+        let data = encode(&[10, 20, 30, 40], 2, 2, ExtendedColorType::L8);
+        let image = Decoder::new(&data).decode().unwrap();
+        assert_eq!(image.layout(), PixelLayout::Rgb);
+        assert_eq!(&image[..6], &[10, 10, 10, 20, 20, 20]);
+
+        let buf: Vec<u8> = [0u16, 257 * 50, u16::MAX, 257 * 128]
+            .iter()
+            .flat_map(|v| v.to_ne_bytes())
+            .collect();
+        let data = encode(&buf, 1, 1, ExtendedColorType::Rgba16);
+        let image = Decoder::new(&data).decode().unwrap();
+        assert_eq!(&*image, &[0, 50, 255, 128]);
+
+        let image = Rgb32FImage::from_raw(1, 1, vec![0.0, 0.5, 1.5]).unwrap();
+        let mut data = Vec::new();
+        DynamicImage::from(image)
+            .write_with_encoder(WebPEncoder::new_lossless(&mut data))
+            .unwrap();
+        let image = Decoder::new(&data).decode().unwrap();
+        assert_eq!(&*image, &[0, 128, 255]);
+    }
+
+    #[test]
+    fn test_image_encoder_settings() {
+        let image = RgbaImage::from_pixel(8, 8, [200, 100, 50, 255].into());
+        let mut lossy = Vec::new();
+        let mut encoder = WebPEncoder::new_with_quality(&mut lossy, 90.0);
+        encoder.set_icc_profile(b"icc".to_vec());
+        encoder.set_exif(b"exif".to_vec());
+        encoder
+            .write_image(image.as_raw(), 8, 8, ExtendedColorType::Rgba8)
+            .unwrap();
+        let metadata = Decoder::new(&lossy).metadata().unwrap();
+        assert_eq!(metadata.icc_profile.as_deref(), Some(&b"icc"[..]));
+        assert_eq!(metadata.exif.as_deref(), Some(&b"exif"[..]));
+        assert!(matches!(
+            Decoder::new(&lossy).info().unwrap().format(),
+            crate::CompressionFormat::Lossy
+        ));
+
+        let result = WebPEncoder::new(Vec::new()).write_image(&[0], 8, 1, ExtendedColorType::L1);
+        assert!(matches!(result, Err(ImageError::Unsupported(_))));
+    }
+}
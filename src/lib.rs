@@ -24,6 +24,63 @@ mod shared;
 #[doc(inline)]
 pub use shared::*;
 
+mod demux;
+
+mod metadata;
+#[doc(inline)]
+pub use metadata::*;
+
+mod mux;
+pub use libwebp_sys::{
+    WebPChunkId, WebPFeatureFlags, WebPMuxAnimBlend, WebPMuxAnimDispose, WebPMuxAnimParams,
+    WebPMuxError,
+};
+#[doc(inline)]
+pub use mux::*;
+
+mod animation_edit;
+#[doc(inline)]
+pub use animation_edit::*;
+
+#[cfg(feature = "img")]
+mod convert;
+
+#[cfg(feature = "img")]
+mod image_encoder;
+#[cfg(feature = "img")]
+#[doc(inline)]
+pub use image_encoder::*;
+
+#[cfg(feature = "img")]
+mod image_decoder;
+#[cfg(feature = "img")]
+#[doc(inline)]
+pub use image_decoder::*;
+
+mod picture;
+#[doc(inline)]
+pub use picture::*;
+
+mod distortion;
+#[doc(inline)]
+pub use distortion::*;
+
+mod quality_search;
+#[doc(inline)]
+pub use quality_search::*;
+
+mod auto_encode;
+#[doc(inline)]
+pub use auto_encode::*;
+
+mod batch;
+#[doc(inline)]
+pub use batch::*;
+
+mod container;
+#[doc(inline)]
+pub use container::*;
+
 pub use libwebp_sys::WebPConfig;
 
 #[cfg(test)]
@@ -95,32 +152,17 @@ mod tests {
         assert_eq!(test_image_no_alpha.to_rgb8().deref(), decoded.deref());
 
         let test_image_alpha = generate_color_wheel(SIZE, SIZE, true);
-        let encoded = Encoder::from_image(&test_image_alpha)
-            .unwrap()
-            .encode_lossless();
+        let mut encoder = Encoder::from_image(&test_image_alpha).unwrap();
+        // Without exact mode, libwebp changes the RGB values in transparent regions to compress better.
+        encoder.set_exact(true);
+        let encoded = encoder.encode_lossless();
 
         let decoded = Decoder::new(encoded.deref())
             .decode()
             .unwrap()
             .to_image()
             .to_rgba8();
-
-        // To achieve better compression, the webp library changes the rgb values in transparent regions
-        // This means we have to exclusively compare the opaque regions
-        // See the note for WebPEncodeLossless* at https://developers.google.com/speed/webp/docs/api#simple_encoding_api
-        fn compare(p1: &Rgba<u8>, p2: &Rgba<u8>) -> bool {
-            // two pixels are equal if they are fully transparent
-            if p1.channels()[3] == 0 && p2.channels()[3] == 0 {
-                true
-            } else {
-                // or if they otherwise equal
-                p1 == p2
-            }
-        }
-
-        for (p1, p2) in test_image_alpha.to_rgba8().pixels().zip(decoded.pixels()) {
-            assert!(compare(p1, p2))
-        }
+        assert_eq!(test_image_alpha.to_rgba8().deref(), decoded.deref());
     }
 
     #[test]
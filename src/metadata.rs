@@ -0,0 +1,320 @@
+use std::ops::{BitOr, BitOrAssign};
+
+use libwebp_sys::{WebPDemuxer, WebPMuxError};
+
+use crate::demux::{get_chunk, Demuxer};
+use crate::shared::WebPMemory;
+use crate::Mux;
+
+/// The metadata stored in the `ICCP`, `EXIF` and `XMP ` chunks of a WebP file.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Metadata {
+    /// The ICC color profile.
+    pub icc_profile: Option<Vec<u8>>,
+    /// The EXIF metadata, usually a TIFF structure optionally preceded by `Exif\0\0`.
+    pub exif: Option<Vec<u8>>,
+    /// The XMP metadata, an XML document.
+    pub xmp: Option<Vec<u8>>,
+}
+
+impl Metadata {
+    /// Reads the metadata chunks from the given WebP file.
+    /// Returns `None` if the data isn't a valid WebP file.
+    pub fn new(data: &[u8]) -> Option<Self> {
+        let demuxer = Demuxer::new(data)?;
+        Some(unsafe { Self::from_demuxer(demuxer.as_ptr()) })
+    }
+
+    /// # Safety
+    /// The demuxer must be valid.
+    pub(crate) unsafe fn from_demuxer(demuxer: *const WebPDemuxer) -> Self {
+        Self {
+            icc_profile: get_chunk(demuxer, b"ICCP").map(<[u8]>::to_vec),
+            exif: get_chunk(demuxer, b"EXIF").map(<[u8]>::to_vec),
+            xmp: get_chunk(demuxer, b"XMP ").map(<[u8]>::to_vec),
+        }
+    }
+
+    /// Returns true if none of the metadata chunks are present.
+    pub fn is_empty(&self) -> bool {
+        self.icc_profile.is_none() && self.exif.is_none() && self.xmp.is_none()
+    }
+
+    /// Returns the value of the EXIF orientation tag, between 1 and 8, if present.
+    ///
+    /// The orientation describes how the image has to be rotated or flipped to be displayed upright.
+    pub fn exif_orientation(&self) -> Option<u16> {
+        let exif = self.exif.as_deref()?;
+        let tiff = exif.strip_prefix(b"Exif\0\0").unwrap_or(exif);
+
+        let big_endian = match tiff.get(0..2)? {
+            b"II" => false,
+            b"MM" => true,
+            _ => return None,
+        };
+        let u16_at = |offset: usize| -> Option<u16> {
+            let bytes = tiff.get(offset..offset.checked_add(2)?)?;
+            let bytes = [bytes[0], bytes[1]];
+            Some(if big_endian {
+                u16::from_be_bytes(bytes)
+            } else {
+                u16::from_le_bytes(bytes)
+            })
+        };
+        let u32_at = |offset: usize| -> Option<u32> {
+            let bytes = tiff.get(offset..offset.checked_add(4)?)?;
+            let bytes = [bytes[0], bytes[1], bytes[2], bytes[3]];
+            Some(if big_endian {
+                u32::from_be_bytes(bytes)
+            } else {
+                u32::from_le_bytes(bytes)
+            })
+        };
+        if u16_at(2)? != 42 {
+            return None;
+        }
+
+        const ORIENTATION_TAG: u16 = 0x0112;
+        const SHORT_TYPE: u16 = 3;
+        // The offsets come from the file, so overflowing them means the data is invalid.
+        let ifd = usize::try_from(u32_at(4)?).ok()?;
+        let entries = u16_at(ifd)?;
+        (0..usize::from(entries))
+            .map_while(|i| i.checked_mul(12)?.checked_add(ifd)?.checked_add(2))
+            .find(|&entry| u16_at(entry) == Some(ORIENTATION_TAG))
+            .filter(|&entry| entry.checked_add(2).and_then(u16_at) == Some(SHORT_TYPE))
+            .and_then(|entry| u16_at(entry.checked_add(8)?))
+            .filter(|orientation| (1..=8).contains(orientation))
+    }
+}
+
+/// A set of metadata kinds, which can be combined with `|`.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct MetadataKinds(u8);
+
+impl MetadataKinds {
+    pub const NONE: Self = Self(0);
+    /// The `ICCP` chunk.
+    pub const ICC_PROFILE: Self = Self(1);
+    /// The `EXIF` chunk.
+    pub const EXIF: Self = Self(1 << 1);
+    /// The `XMP ` chunk.
+    pub const XMP: Self = Self(1 << 2);
+    pub const ALL: Self = Self(Self::ICC_PROFILE.0 | Self::EXIF.0 | Self::XMP.0);
+
+    /// Returns true if all kinds in `other` are also in `self`.
+    pub fn contains(self, other: Self) -> bool {
+        self.0 & other.0 == other.0
+    }
+}
+
+impl BitOr for MetadataKinds {
+    type Output = Self;
+
+    fn bitor(self, rhs: Self) -> Self {
+        Self(self.0 | rhs.0)
+    }
+}
+
+impl BitOrAssign for MetadataKinds {
+    fn bitor_assign(&mut self, rhs: Self) {
+        self.0 |= rhs.0;
+    }
+}
+
+const METADATA_CHUNKS: [(MetadataKinds, &[u8; 4]); 3] = [
+    (MetadataKinds::ICC_PROFILE, b"ICCP"),
+    (MetadataKinds::EXIF, b"EXIF"),
+    (MetadataKinds::XMP, b"XMP "),
+];
+
+/// Reads the ICC profile, EXIF and XMP metadata from the given WebP file.
+/// Returns `None` if the data isn't a valid WebP file.
+pub fn read_metadata(data: &[u8]) -> Option<Metadata> {
+    Metadata::new(data)
+}
+
+/// Removes the given kinds of metadata from a WebP file.
+///
+/// Only the container is rewritten, the image bitstreams are copied unchanged so no quality is lost.
+pub fn strip_metadata(data: &[u8], which: MetadataKinds) -> Result<WebPMemory, WebPMuxError> {
+    let mut mux = Mux::from_data(data)?;
+    for (kind, fourcc) in METADATA_CHUNKS {
+        if which.contains(kind) {
+            delete_if_present(&mut mux, fourcc)?;
+        }
+    }
+    mux.assemble()
+}
+
+/// Replaces the metadata of a WebP file with the given one.
+/// Every chunk which is `None` in `metadata` is removed from the file.
+///
+/// Only the container is rewritten, the image bitstreams are copied unchanged so no quality is lost.
+pub fn replace_metadata(data: &[u8], metadata: &Metadata) -> Result<WebPMemory, WebPMuxError> {
+    let mut mux = Mux::from_data(data)?;
+    let payloads = [&metadata.icc_profile, &metadata.exif, &metadata.xmp];
+    for ((_, fourcc), payload) in METADATA_CHUNKS.into_iter().zip(payloads) {
+        match payload {
+            Some(payload) => mux.set_chunk(fourcc, payload)?,
+            None => delete_if_present(&mut mux, fourcc)?,
+        }
+    }
+    mux.assemble()
+}
+
+fn delete_if_present(mux: &mut Mux, fourcc: &[u8; 4]) -> Result<(), WebPMuxError> {
+    match mux.delete_chunk(fourcc) {
+        Err(WebPMuxError::WEBP_MUX_NOT_FOUND) => Ok(()),
+        res => res,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Encoder;
+
+    fn exif_with_orientation(big_endian: bool, orientation: u16) -> Vec<u8> {
+        let u16_bytes = |v: u16| {
+            if big_endian {
+                v.to_be_bytes()
+            } else {
+                v.to_le_bytes()
+            }
+        };
+        let u32_bytes = |v: u32| {
+            if big_endian {
+                v.to_be_bytes()
+            } else {
+                v.to_le_bytes()
+            }
+        };
+        let mut exif = b"Exif\0\0".to_vec();
+        exif.extend_from_slice(if big_endian { b"MM" } else { b"II" });
+        exif.extend_from_slice(&u16_bytes(42));
+        exif.extend_from_slice(&u32_bytes(8));
+        exif.extend_from_slice(&u16_bytes(1));
+        exif.extend_from_slice(&u16_bytes(0x0112));
+        exif.extend_from_slice(&u16_bytes(3));
+        exif.extend_from_slice(&u32_bytes(1));
+        exif.extend_from_slice(&u16_bytes(orientation));
+        exif.extend_from_slice(&[0, 0]);
+        exif.extend_from_slice(&u32_bytes(0));
+        exif
+    }
+
+    #[test]
+    fn test_read_metadata() {
+        let image = [100u8; 2 * 2 * 3];
+        let icc = b"fake icc profile";
+        let xmp = b"<x:xmpmeta/>";
+        let mut encoder = Encoder::from_rgb(&image, 2, 2);
+        encoder.set_icc_profile(icc);
+        encoder.set_xmp(xmp);
+        let webp = encoder.encode(75.0);
+
+        let metadata = read_metadata(&webp).unwrap();
+        assert_eq!(metadata.icc_profile.as_deref(), Some(&icc[..]));
+        assert_eq!(metadata.exif, None);
+        assert_eq!(metadata.xmp.as_deref(), Some(&xmp[..]));
+        assert!(!metadata.is_empty());
+
+        let plain = Encoder::from_rgb(&image, 2, 2).encode(75.0);
+        assert!(read_metadata(&plain).unwrap().is_empty());
+        assert!(read_metadata(&[0u8; 16]).is_none());
+    }
+
+    fn image_chunk(webp: &[u8]) -> Vec<u8> {
+        Mux::from_data(webp)
+            .unwrap()
+            .get_frame(0)
+            .unwrap()
+            .bitstream
+            .to_vec()
+    }
+
+    #[test]
+    fn test_metadata_kinds() {
+        let kinds = MetadataKinds::EXIF | MetadataKinds::XMP;
+        assert!(kinds.contains(MetadataKinds::EXIF));
+        assert!(!kinds.contains(MetadataKinds::ICC_PROFILE));
+        assert!(MetadataKinds::ALL.contains(kinds));
+        assert!(kinds.contains(MetadataKinds::NONE));
+        let mut kinds = MetadataKinds::NONE;
+        kinds |= MetadataKinds::ICC_PROFILE;
+        assert_eq!(kinds, MetadataKinds::ICC_PROFILE);
+    }
+
+    #[test]
+    fn test_strip_and_replace_metadata() {
+        let image: Vec<u8> = (0..8 * 8 * 3).map(|i| (i * 7) as u8).collect();
+        let mut encoder = Encoder::from_rgb(&image, 8, 8);
+        encoder.set_icc_profile(b"icc");
+        encoder.set_exif(b"exif with gps");
+        encoder.set_xmp(b"xmp");
+        let webp = encoder.encode(80.0);
+
+        let stripped = strip_metadata(&webp, MetadataKinds::EXIF).unwrap();
+        let metadata = read_metadata(&stripped).unwrap();
+        assert_eq!(metadata.icc_profile.as_deref(), Some(&b"icc"[..]));
+        assert_eq!(metadata.exif, None);
+        assert_eq!(metadata.xmp.as_deref(), Some(&b"xmp"[..]));
+        assert_eq!(image_chunk(&stripped), image_chunk(&webp));
+
+        let stripped = strip_metadata(&stripped, MetadataKinds::ALL).unwrap();
+        assert!(read_metadata(&stripped).unwrap().is_empty());
+        assert_eq!(image_chunk(&stripped), image_chunk(&webp));
+
+        let replaced = replace_metadata(
+            &webp,
+            &Metadata {
+                exif: Some(b"new exif".to_vec()),
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        let metadata = read_metadata(&replaced).unwrap();
+        assert_eq!(metadata.icc_profile, None);
+        assert_eq!(metadata.exif.as_deref(), Some(&b"new exif"[..]));
+        assert_eq!(metadata.xmp, None);
+        assert_eq!(image_chunk(&replaced), image_chunk(&webp));
+
+        assert!(strip_metadata(&[0u8; 12], MetadataKinds::ALL).is_err());
+    }
+
+    #[test]
+    fn test_exif_orientation() {
+        for big_endian in [false, true] {
+            let metadata = Metadata {
+                exif: Some(exif_with_orientation(big_endian, 6)),
+                ..Default::default()
+            };
+            assert_eq!(metadata.exif_orientation(), Some(6));
+        }
+
+        let metadata = Metadata {
+            exif: Some(exif_with_orientation(false, 42)),
+            ..Default::default()
+        };
+        assert_eq!(metadata.exif_orientation(), None);
+
+        let mut truncated = exif_with_orientation(true, 3);
+        truncated.truncate(20);
+        let metadata = Metadata {
+            exif: Some(truncated),
+            ..Default::default()
+        };
+        assert_eq!(metadata.exif_orientation(), None);
+
+        // An IFD offset at the end of the address space.
+        let mut far = exif_with_orientation(false, 3);
+        far[10..14].copy_from_slice(&u32::MAX.to_le_bytes());
+        let metadata = Metadata {
+            exif: Some(far),
+            ..Default::default()
+        };
+        assert_eq!(metadata.exif_orientation(), None);
+        assert_eq!(Metadata::default().exif_orientation(), None);
+    }
+}
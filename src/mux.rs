@@ -0,0 +1,383 @@
+use std::fmt::{Debug, Error, Formatter};
+
+use libwebp_sys::*;
+
+use crate::shared::WebPMemory;
+
+/// A safe wrapper around libwebp's `WebPMux`, which edits the chunks and frames of a WebP file
+/// without re-encoding any pixels.
+///
+/// All data passed to a `Mux` is copied, so it never borrows from its inputs.
+/// The mux is freed when it is dropped.
+pub struct Mux {
+    ptr: *mut WebPMux,
+}
+
+/// A frame of an animation held by a [Mux].
+#[derive(Debug)]
+pub struct MuxFrame {
+    /// The frame's image as a single-image WebP file or a raw VP8/VP8L bitstream.
+    pub bitstream: WebPMemory,
+    pub params: MuxFrameParams,
+}
+
+/// The placement and timing of a frame within an animation.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct MuxFrameParams {
+    /// Horizontal offset of the frame within the canvas. Odd offsets are rounded down by libwebp.
+    pub x_offset: u32,
+    /// Vertical offset of the frame within the canvas. Odd offsets are rounded down by libwebp.
+    pub y_offset: u32,
+    /// How long the frame is displayed, in milliseconds.
+    pub duration: u32,
+    pub dispose_method: WebPMuxAnimDispose,
+    pub blend_method: WebPMuxAnimBlend,
+}
+
+impl Default for MuxFrameParams {
+    fn default() -> Self {
+        Self {
+            x_offset: 0,
+            y_offset: 0,
+            duration: 0,
+            dispose_method: WebPMuxAnimDispose::WEBP_MUX_DISPOSE_NONE,
+            blend_method: WebPMuxAnimBlend::WEBP_MUX_BLEND,
+        }
+    }
+}
+
+/// Chunk kinds that can be counted with [Mux::num_chunks], in the order they appear in a file.
+const CHUNK_IDS: [WebPChunkId; 9] = [
+    WebPChunkId::WEBP_CHUNK_VP8X,
+    WebPChunkId::WEBP_CHUNK_ICCP,
+    WebPChunkId::WEBP_CHUNK_ANIM,
+    WebPChunkId::WEBP_CHUNK_ANMF,
+    WebPChunkId::WEBP_CHUNK_ALPHA,
+    WebPChunkId::WEBP_CHUNK_IMAGE,
+    WebPChunkId::WEBP_CHUNK_EXIF,
+    WebPChunkId::WEBP_CHUNK_XMP,
+    WebPChunkId::WEBP_CHUNK_UNKNOWN,
+];
+
+fn check(mux_error: WebPMuxError) -> Result<(), WebPMuxError> {
+    match mux_error {
+        WebPMuxError::WEBP_MUX_OK => Ok(()),
+        mux_error => Err(mux_error),
+    }
+}
+
+fn webp_data(data: &[u8]) -> WebPData {
+    WebPData {
+        bytes: data.as_ptr(),
+        size: data.len(),
+    }
+}
+
+impl Mux {
+    /// Creates an empty mux.
+    ///
+    /// # Panics
+    /// Panics if libwebp fails to allocate the mux, like Rust collections do when they run out of memory.
+    pub fn new() -> Self {
+        let ptr = WebPMuxNew();
+        assert!(!ptr.is_null(), "Failed to allocate a WebPMux");
+        Self { ptr }
+    }
+
+    /// Creates a mux from the given WebP file, copying its contents.
+    pub fn from_data(data: &[u8]) -> Result<Self, WebPMuxError> {
+        let ptr = unsafe { WebPMuxCreateInternal(&webp_data(data), 1, WebPGetMuxABIVersion()) };
+        if ptr.is_null() {
+            return Err(WebPMuxError::WEBP_MUX_BAD_DATA);
+        }
+        Ok(Self { ptr })
+    }
+
+    /// Returns the payload of the chunk with the given FourCC, e.g. `b"ICCP"`, `b"EXIF"` or `b"XMP "`.
+    /// Image related chunks (`ANMF`, `ALPH`, `VP8 ` and `VP8L`) can't be accessed this way, use [get_frame](Mux::get_frame) instead.
+    pub fn get_chunk(&self, fourcc: &[u8; 4]) -> Option<&[u8]> {
+        let mut data = WebPData::default();
+        let mux_error =
+            unsafe { WebPMuxGetChunk(self.ptr, fourcc.as_ptr() as *const _, &mut data) };
+        if mux_error != WebPMuxError::WEBP_MUX_OK {
+            return None;
+        }
+        // The chunk data is owned by the mux and stays valid until the mux is modified, which requires `&mut self`.
+        Some(unsafe { std::slice::from_raw_parts(data.bytes, data.size) })
+    }
+
+    /// Sets the chunk with the given FourCC, replacing an existing one.
+    pub fn set_chunk(&mut self, fourcc: &[u8; 4], data: &[u8]) -> Result<(), WebPMuxError> {
+        check(unsafe {
+            WebPMuxSetChunk(self.ptr, fourcc.as_ptr() as *const _, &webp_data(data), 1)
+        })
+    }
+
+    /// Deletes all chunks with the given FourCC.
+    /// Returns `WEBP_MUX_NOT_FOUND` if there are none.
+    pub fn delete_chunk(&mut self, fourcc: &[u8; 4]) -> Result<(), WebPMuxError> {
+        check(unsafe { WebPMuxDeleteChunk(self.ptr, fourcc.as_ptr() as *const _) })
+    }
+
+    /// Returns the number of chunks of the given kind.
+    pub fn num_chunks(&self, id: WebPChunkId) -> Result<usize, WebPMuxError> {
+        let mut num = 0;
+        check(unsafe { WebPMuxNumChunks(self.ptr, id, &mut num) })?;
+        Ok(num as usize)
+    }
+
+    /// Lists the kinds of chunks present in the mux along with their number.
+    pub fn chunks(&self) -> Result<Vec<(WebPChunkId, usize)>, WebPMuxError> {
+        let mut chunks = vec![];
+        for id in CHUNK_IDS {
+            let num = self.num_chunks(id)?;
+            if num > 0 {
+                chunks.push((id, num));
+            }
+        }
+        Ok(chunks)
+    }
+
+    /// Returns the `WebPFeatureFlags` describing the contents of the mux.
+    pub fn features(&self) -> Result<u32, WebPMuxError> {
+        let mut flags = 0;
+        check(unsafe { WebPMuxGetFeatures(self.ptr, &mut flags) })?;
+        Ok(flags)
+    }
+
+    /// Returns the canvas size in pixels.
+    pub fn canvas_size(&self) -> Result<(u32, u32), WebPMuxError> {
+        let (mut width, mut height) = (0, 0);
+        check(unsafe { WebPMuxGetCanvasSize(self.ptr, &mut width, &mut height) })?;
+        Ok((width as u32, height as u32))
+    }
+
+    /// Sets the canvas size in pixels. If it is `(0, 0)`, the canvas is sized to fit all frames when assembling.
+    pub fn set_canvas_size(&mut self, width: u32, height: u32) -> Result<(), WebPMuxError> {
+        let width = i32::try_from(width).map_err(|_| WebPMuxError::WEBP_MUX_INVALID_ARGUMENT)?;
+        let height = i32::try_from(height).map_err(|_| WebPMuxError::WEBP_MUX_INVALID_ARGUMENT)?;
+        check(unsafe { WebPMuxSetCanvasSize(self.ptr, width, height) })
+    }
+
+    /// Returns the loop count and background color stored in the `ANIM` chunk.
+    pub fn animation_params(&self) -> Result<WebPMuxAnimParams, WebPMuxError> {
+        let mut params = WebPMuxAnimParams {
+            bgcolor: 0,
+            loop_count: 0,
+        };
+        check(unsafe { WebPMuxGetAnimationParams(self.ptr, &mut params) })?;
+        Ok(params)
+    }
+
+    /// Sets the loop count and background color of the animation.
+    pub fn set_animation_params(&mut self, params: &WebPMuxAnimParams) -> Result<(), WebPMuxError> {
+        check(unsafe { WebPMuxSetAnimationParams(self.ptr, params) })
+    }
+
+    /// Sets the (non-animated) image, removing all existing images and frames.
+    /// The bitstream can be a raw VP8/VP8L bitstream or a single-image WebP file.
+    pub fn set_image(&mut self, bitstream: &[u8]) -> Result<(), WebPMuxError> {
+        check(unsafe { WebPMuxSetImage(self.ptr, &webp_data(bitstream), 1) })
+    }
+
+    /// Returns the number of frames, which is 1 for a still image.
+    pub fn num_frames(&self) -> Result<usize, WebPMuxError> {
+        let anim = self.num_chunks(WebPChunkId::WEBP_CHUNK_ANMF)?;
+        if anim > 0 {
+            return Ok(anim);
+        }
+        self.num_chunks(WebPChunkId::WEBP_CHUNK_IMAGE)
+    }
+
+    /// Returns a copy of the frame at the given index, starting at 0.
+    pub fn get_frame(&self, index: usize) -> Result<MuxFrame, WebPMuxError> {
+        // libwebp counts frames from 1, 0 means the last frame.
+        let nth = u32::try_from(index)
+            .ok()
+            .and_then(|i| i.checked_add(1))
+            .ok_or(WebPMuxError::WEBP_MUX_NOT_FOUND)?;
+        let mut info: WebPMuxFrameInfo = unsafe { std::mem::zeroed() };
+        check(unsafe { WebPMuxGetFrame(self.ptr, nth, &mut info) })?;
+        Ok(MuxFrame {
+            bitstream: WebPMemory(info.bitstream.bytes as *mut u8, info.bitstream.size),
+            params: MuxFrameParams {
+                x_offset: info.x_offset as u32,
+                y_offset: info.y_offset as u32,
+                duration: info.duration as u32,
+                dispose_method: info.dispose_method,
+                blend_method: info.blend_method,
+            },
+        })
+    }
+
+    /// Adds an animation frame after all existing ones.
+    /// The bitstream can be a raw VP8/VP8L bitstream or a single-image WebP file, which may contain an `ALPH` chunk.
+    pub fn push_frame(
+        &mut self,
+        bitstream: &[u8],
+        params: &MuxFrameParams,
+    ) -> Result<(), WebPMuxError> {
+        let to_int = |v: u32| i32::try_from(v).map_err(|_| WebPMuxError::WEBP_MUX_INVALID_ARGUMENT);
+        let info = WebPMuxFrameInfo {
+            bitstream: webp_data(bitstream),
+            x_offset: to_int(params.x_offset)?,
+            y_offset: to_int(params.y_offset)?,
+            duration: to_int(params.duration)?,
+            id: WebPChunkId::WEBP_CHUNK_ANMF,
+            dispose_method: params.dispose_method,
+            blend_method: params.blend_method,
+            pad: [0],
+        };
+        check(unsafe { WebPMuxPushFrame(self.ptr, &info, 1) })
+    }
+
+    /// Deletes the frame at the given index, starting at 0.
+    pub fn delete_frame(&mut self, index: usize) -> Result<(), WebPMuxError> {
+        let nth = u32::try_from(index)
+            .ok()
+            .and_then(|i| i.checked_add(1))
+            .ok_or(WebPMuxError::WEBP_MUX_NOT_FOUND)?;
+        check(unsafe { WebPMuxDeleteFrame(self.ptr, nth) })
+    }
+
+    /// Sets the ICCP, EXIF and XMP chunks for every payload which is present.
+    pub(crate) fn set_metadata_chunks(
+        &mut self,
+        icc_profile: Option<&[u8]>,
+        exif: Option<&[u8]>,
+        xmp: Option<&[u8]>,
+    ) -> Result<(), WebPMuxError> {
+        let chunks = [(b"ICCP", icc_profile), (b"EXIF", exif), (b"XMP ", xmp)];
+        for (fourcc, payload) in chunks {
+            if let Some(payload) = payload {
+                self.set_chunk(fourcc, payload)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Assembles the contents of the mux into a WebP file.
+    pub fn assemble(&mut self) -> Result<WebPMemory, WebPMuxError> {
+        let mut data = WebPData::default();
+        check(unsafe { WebPMuxAssemble(self.ptr, &mut data) })?;
+        Ok(WebPMemory(data.bytes as *mut u8, data.size))
+    }
+}
+
+impl Default for Mux {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Debug for Mux {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result<(), Error> {
+        f.debug_struct("Mux").finish()
+    }
+}
+
+impl Drop for Mux {
+    fn drop(&mut self) {
+        unsafe { WebPMuxDelete(self.ptr) }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{AnimDecoder, AnimEncoder, AnimFrame, Encoder};
+
+    fn still_image() -> WebPMemory {
+        let image = [10u8, 20, 30].repeat(4 * 4);
+        Encoder::from_rgb(&image, 4, 4).encode_lossless()
+    }
+
+    #[test]
+    fn test_mux_chunks() {
+        let webp = still_image();
+        let mut mux = Mux::from_data(&webp).unwrap();
+        assert_eq!(mux.canvas_size().unwrap(), (4, 4));
+        assert_eq!(mux.num_frames().unwrap(), 1);
+        assert!(mux.get_chunk(b"ICCP").is_none());
+
+        mux.set_chunk(b"ICCP", b"icc").unwrap();
+        mux.set_chunk(b"XMP ", b"xmp").unwrap();
+        assert_eq!(mux.get_chunk(b"ICCP"), Some(&b"icc"[..]));
+        mux.delete_chunk(b"XMP ").unwrap();
+        assert_eq!(
+            mux.delete_chunk(b"XMP "),
+            Err(WebPMuxError::WEBP_MUX_NOT_FOUND)
+        );
+
+        let assembled = mux.assemble().unwrap();
+        let mux = Mux::from_data(&assembled).unwrap();
+        assert_eq!(mux.get_chunk(b"ICCP"), Some(&b"icc"[..]));
+        assert!(mux.get_chunk(b"XMP ").is_none());
+        let flags = mux.features().unwrap();
+        assert_ne!(flags & WebPFeatureFlags::ICCP_FLAG as u32, 0);
+        assert_eq!(flags & WebPFeatureFlags::XMP_FLAG as u32, 0);
+        let chunks = mux.chunks().unwrap();
+        assert!(chunks.contains(&(WebPChunkId::WEBP_CHUNK_ICCP, 1)));
+        assert!(chunks.contains(&(WebPChunkId::WEBP_CHUNK_IMAGE, 1)));
+    }
+
+    #[test]
+    fn test_mux_invalid_data() {
+        assert_eq!(
+            Mux::from_data(&[0u8; 12]).unwrap_err(),
+            WebPMuxError::WEBP_MUX_BAD_DATA
+        );
+    }
+
+    #[test]
+    fn test_mux_frames() {
+        let frame = still_image();
+        let mut mux = Mux::new();
+        let params = MuxFrameParams {
+            duration: 100,
+            ..Default::default()
+        };
+        mux.push_frame(&frame, &params).unwrap();
+        mux.push_frame(
+            &frame,
+            &MuxFrameParams {
+                duration: 200,
+                ..params
+            },
+        )
+        .unwrap();
+        mux.set_animation_params(&WebPMuxAnimParams {
+            bgcolor: 0,
+            loop_count: 4,
+        })
+        .unwrap();
+        assert_eq!(mux.num_frames().unwrap(), 2);
+        assert_eq!(mux.get_frame(1).unwrap().params.duration, 200);
+        assert!(mux.get_frame(2).is_err());
+        mux.delete_frame(0).unwrap();
+        assert_eq!(mux.get_frame(0).unwrap().params.duration, 200);
+        mux.push_frame(&frame, &params).unwrap();
+
+        let webp = mux.assemble().unwrap();
+        let anim = AnimDecoder::new(&webp).decode().unwrap();
+        assert_eq!(anim.len(), 2);
+        assert_eq!(anim.loop_count, 4);
+        assert_eq!(anim.get_frame(1).unwrap().get_time_ms(), 300);
+    }
+
+    #[test]
+    fn test_mux_animation_params() {
+        let config = WebPConfig::new().unwrap();
+        let black = [0u8; 2 * 2 * 4];
+        let white = [255u8; 2 * 2 * 4];
+        let mut encoder = AnimEncoder::new(2, 2, &config);
+        encoder.set_loop_count(7);
+        encoder.add_frame(AnimFrame::from_rgba(&black, 2, 2, 0));
+        encoder.add_frame(AnimFrame::from_rgba(&white, 2, 2, 50));
+        let webp = encoder.encode();
+
+        let mux = Mux::from_data(&webp).unwrap();
+        assert_eq!(mux.animation_params().unwrap().loop_count, 7);
+        assert_eq!(mux.num_frames().unwrap(), 2);
+    }
+}
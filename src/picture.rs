@@ -0,0 +1,476 @@
+use std::marker::PhantomData;
+
+use libwebp_sys::*;
+
+use crate::shared::{min_buffer_len, ManageedPicture, PixelLayout, WebPImage};
+
+/// A picture in libwebp's own ARGB format, which can be cropped, rescaled and flipped before encoding.
+///
+/// Owned pictures are `Picture<'static>`. A view created with [view](Picture::view) shares
+/// the pixels of the picture it was created from and borrows it for `'a`.
+/// Pass a picture to [Encoder::from_picture](crate::Encoder::from_picture) to encode it.
+pub struct Picture<'a> {
+    picture: ManageedPicture,
+    layout: PixelLayout,
+    _source: PhantomData<&'a ()>,
+}
+
+// The pixels are only modified through `&mut self`, and a view borrows the picture it shares them with.
+unsafe impl Send for Picture<'_> {}
+unsafe impl Sync for Picture<'_> {}
+
+impl Picture<'static> {
+    /// Imports the given image data into a new picture.
+    /// The image data must be in the pixel layout of the layout parameter.
+    /// Returns `VP8_ENC_ERROR_BAD_DIMENSION` if the buffer is too small for the given dimensions.
+    pub fn new(
+        image: &[u8],
+        layout: PixelLayout,
+        width: u32,
+        height: u32,
+    ) -> Result<Self, WebPEncodingError> {
+        let stride = width as usize * usize::from(layout.bytes_per_pixel());
+        Self::with_stride(image, layout, width, height, stride)
+    }
+
+    /// Imports image data whose rows start `stride` bytes apart into a new picture,
+    /// like padded rows or a sub-rectangle of a larger image.
+    /// Returns `VP8_ENC_ERROR_BAD_DIMENSION` if the stride is shorter than a row
+    /// or the buffer is too small for the given dimensions and stride.
+    pub fn with_stride(
+        image: &[u8],
+        layout: PixelLayout,
+        width: u32,
+        height: u32,
+        stride: usize,
+    ) -> Result<Self, WebPEncodingError> {
+        let row_len = (width as usize).saturating_mul(layout.bytes_per_pixel().into());
+        if stride < row_len || image.len() < min_buffer_len(layout, width, height, stride) {
+            return Err(WebPEncodingError::VP8_ENC_ERROR_BAD_DIMENSION);
+        }
+        let (Ok(width), Ok(height), Ok(stride)) = (
+            i32::try_from(width),
+            i32::try_from(height),
+            i32::try_from(stride),
+        ) else {
+            return Err(WebPEncodingError::VP8_ENC_ERROR_BAD_DIMENSION);
+        };
+
+        let mut picture = ManageedPicture(WebPPicture::new().unwrap());
+        picture.use_argb = 1;
+        picture.width = width;
+        picture.height = height;
+        let ok = unsafe {
+            match layout {
+                PixelLayout::Rgba => WebPPictureImportRGBA(&mut *picture, image.as_ptr(), stride),
+                PixelLayout::Rgb => WebPPictureImportRGB(&mut *picture, image.as_ptr(), stride),
+            }
+        };
+        if ok == 0 {
+            return Err(picture.error_code);
+        }
+        Ok(Self {
+            picture,
+            layout,
+            _source: PhantomData,
+        })
+    }
+}
+
+impl<'a> Picture<'a> {
+    pub fn width(&self) -> u32 {
+        self.picture.width as u32
+    }
+
+    pub fn height(&self) -> u32 {
+        self.picture.height as u32
+    }
+
+    /// Returns the layout the picture was imported from, which is also the layout of [to_vec](Picture::to_vec).
+    pub fn layout(&self) -> PixelLayout {
+        self.layout
+    }
+
+    /// Returns whether the picture shares its pixels with another one.
+    pub fn is_view(&self) -> bool {
+        unsafe { WebPPictureIsView(&*self.picture) != 0 }
+    }
+
+    /// Keeps only the given rectangle of the picture.
+    /// Returns `VP8_ENC_ERROR_BAD_DIMENSION` if the rectangle is empty or not inside the picture.
+    pub fn crop(
+        &mut self,
+        left: u32,
+        top: u32,
+        width: u32,
+        height: u32,
+    ) -> Result<(), WebPEncodingError> {
+        self.check_rectangle(left, top, width, height)?;
+        let ok = unsafe {
+            WebPPictureCrop(
+                &mut *self.picture,
+                left as i32,
+                top as i32,
+                width as i32,
+                height as i32,
+            )
+        };
+        check(ok)
+    }
+
+    /// Resizes the picture with libwebp's rescaler.
+    /// If either the width or the height is 0, it is computed to keep the aspect ratio.
+    pub fn rescale(&mut self, width: u32, height: u32) -> Result<(), WebPEncodingError> {
+        if (width == 0 && height == 0) || width > i32::MAX as u32 || height > i32::MAX as u32 {
+            return Err(WebPEncodingError::VP8_ENC_ERROR_BAD_DIMENSION);
+        }
+        let ok = unsafe { WebPPictureRescale(&mut *self.picture, width as i32, height as i32) };
+        check(ok)
+    }
+
+    /// Returns a picture sharing the pixels of the given rectangle of this one, without copying them.
+    /// Returns `VP8_ENC_ERROR_BAD_DIMENSION` if the rectangle is empty or not inside the picture.
+    pub fn view(
+        &self,
+        left: u32,
+        top: u32,
+        width: u32,
+        height: u32,
+    ) -> Result<Picture<'_>, WebPEncodingError> {
+        self.check_rectangle(left, top, width, height)?;
+        let mut view = ManageedPicture(WebPPicture::new().unwrap());
+        let ok = unsafe {
+            WebPPictureView(
+                &*self.picture,
+                left as i32,
+                top as i32,
+                width as i32,
+                height as i32,
+                &mut *view,
+            )
+        };
+        check(ok)?;
+        Ok(Picture {
+            picture: view,
+            layout: self.layout,
+            _source: PhantomData,
+        })
+    }
+
+    /// Mirrors the picture from left to right.
+    pub fn flip_horizontal(&mut self) -> Result<(), WebPEncodingError> {
+        self.make_owned()?;
+        for y in 0..self.height() as usize {
+            self.row_mut(y).reverse();
+        }
+        Ok(())
+    }
+
+    /// Mirrors the picture from top to bottom.
+    pub fn flip_vertical(&mut self) -> Result<(), WebPEncodingError> {
+        self.make_owned()?;
+        let height = self.height() as usize;
+        for y in 0..height / 2 {
+            let width = self.width() as usize;
+            let stride = self.picture.argb_stride as usize;
+            // The rows don't overlap because `y < height - 1 - y`.
+            unsafe {
+                std::ptr::swap_nonoverlapping(
+                    self.picture.argb.add(y * stride),
+                    self.picture.argb.add((height - 1 - y) * stride),
+                    width,
+                );
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns whether any pixel isn't fully opaque.
+    pub fn has_transparency(&self) -> bool {
+        unsafe { WebPPictureHasTransparency(&*self.picture) != 0 }
+    }
+
+    /// Gives every fully transparent 8x8 block the color of the first one in its run, so that invisible pixels
+    /// compress better. Partially transparent blocks and the leftover pixels at the right and bottom are kept.
+    pub fn cleanup_transparent_area(&mut self) -> Result<(), WebPEncodingError> {
+        self.make_owned()?;
+        unsafe { WebPCleanupTransparentArea(&mut *self.picture) };
+        Ok(())
+    }
+
+    /// Blends the picture onto a background of the given RGB color, making it fully opaque.
+    pub fn blend_alpha(&mut self, background: [u8; 3]) -> Result<(), WebPEncodingError> {
+        self.make_owned()?;
+        let [r, g, b] = background;
+        unsafe { WebPBlendAlpha(&mut *self.picture, u32::from_be_bytes([0, r, g, b])) };
+        Ok(())
+    }
+
+    /// Copies the pixels into a new picture which doesn't borrow anything.
+    pub fn into_owned(self) -> Result<Picture<'static>, WebPEncodingError> {
+        if !self.is_view() {
+            return Ok(Picture {
+                picture: self.picture,
+                layout: self.layout,
+                _source: PhantomData,
+            });
+        }
+        let mut copy = ManageedPicture(WebPPicture::new().unwrap());
+        check(unsafe { WebPPictureCopy(&*self.picture, &mut *copy) })?;
+        Ok(Picture {
+            picture: copy,
+            layout: self.layout,
+            _source: PhantomData,
+        })
+    }
+
+    /// Exports the pixels in the layout the picture was imported from, without padding between rows.
+    pub fn to_vec(&self) -> Vec<u8> {
+        let bpp = usize::from(self.layout.bytes_per_pixel());
+        let mut image = Vec::with_capacity(self.width() as usize * self.height() as usize * bpp);
+        for y in 0..self.height() as usize {
+            for &argb in self.row(y) {
+                let [a, r, g, b] = argb.to_be_bytes();
+                image.extend_from_slice(&[r, g, b, a][..bpp]);
+            }
+        }
+        image
+    }
+
+    pub(crate) fn as_ptr(&self) -> *const WebPPicture {
+        &*self.picture
+    }
+
+    /// Returns a picture which `WebPEncode` can modify with the given configuration without changing this one.
+    ///
+    /// Lossy encoding converts a view to YUV in memory of its own, so the pixels are only copied when
+    /// lossless encoding would replace the colors of transparent pixels.
+    pub(crate) fn for_encoding(
+        &self,
+        config: &WebPConfig,
+    ) -> Result<ManageedPicture, WebPEncodingError> {
+        let mut picture = ManageedPicture(WebPPicture::new().unwrap());
+        let ok = unsafe {
+            if config.lossless != 0 && config.exact == 0 && self.has_transparency() {
+                WebPPictureCopy(&*self.picture, &mut *picture)
+            } else {
+                WebPPictureView(
+                    &*self.picture,
+                    0,
+                    0,
+                    self.picture.width,
+                    self.picture.height,
+                    &mut *picture,
+                )
+            }
+        };
+        check(ok)?;
+        Ok(picture)
+    }
+
+    fn check_rectangle(
+        &self,
+        left: u32,
+        top: u32,
+        width: u32,
+        height: u32,
+    ) -> Result<(), WebPEncodingError> {
+        let inside = |start: u32, len: u32, max: u32| {
+            len > 0 && matches!(start.checked_add(len), Some(end) if end <= max)
+        };
+        if inside(left, width, self.width()) && inside(top, height, self.height()) {
+            Ok(())
+        } else {
+            Err(WebPEncodingError::VP8_ENC_ERROR_BAD_DIMENSION)
+        }
+    }
+
+    /// Copies the pixels of a view, so that they can be modified without changing the picture it borrows.
+    fn make_owned(&mut self) -> Result<(), WebPEncodingError> {
+        if self.is_view() {
+            let mut copy = ManageedPicture(WebPPicture::new().unwrap());
+            check(unsafe { WebPPictureCopy(&*self.picture, &mut *copy) })?;
+            self.picture = copy;
+        }
+        Ok(())
+    }
+
+    fn row(&self, y: usize) -> &[u32] {
+        let stride = self.picture.argb_stride as usize;
+        unsafe {
+            std::slice::from_raw_parts(self.picture.argb.add(y * stride), self.width() as usize)
+        }
+    }
+
+    fn row_mut(&mut self, y: usize) -> &mut [u32] {
+        let stride = self.picture.argb_stride as usize;
+        unsafe {
+            std::slice::from_raw_parts_mut(self.picture.argb.add(y * stride), self.width() as usize)
+        }
+    }
+}
+
+impl TryFrom<&WebPImage> for Picture<'static> {
+    type Error = WebPEncodingError;
+
+    fn try_from(image: &WebPImage) -> Result<Self, Self::Error> {
+        Picture::new(image, image.layout(), image.width(), image.height())
+    }
+}
+
+fn check(ok: i32) -> Result<(), WebPEncodingError> {
+    if ok == 0 {
+        Err(WebPEncodingError::VP8_ENC_ERROR_OUT_OF_MEMORY)
+    } else {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Decoder, Encoder};
+
+    /// A 3x2 RGB picture whose pixels are numbered from 0 to 5.
+    fn numbered() -> Picture<'static> {
+        let image: Vec<u8> = (0..6).flat_map(|i| [i, i, i]).collect();
+        Picture::new(&image, PixelLayout::Rgb, 3, 2).unwrap()
+    }
+
+    fn gray(picture: &Picture) -> Vec<u8> {
+        picture.to_vec().chunks_exact(3).map(|p| p[0]).collect()
+    }
+
+    #[test]
+    fn test_picture_import_export() {
+        let rgba = [1, 2, 3, 4, 0, 0, 0, 0, 9, 10, 11, 12];
+        let picture = Picture::with_stride(&rgba, PixelLayout::Rgba, 1, 2, 8).unwrap();
+        assert_eq!((picture.width(), picture.height()), (1, 2));
+        assert_eq!(picture.layout(), PixelLayout::Rgba);
+        assert_eq!(picture.to_vec(), [1, 2, 3, 4, 9, 10, 11, 12]);
+        assert!(!picture.is_view());
+
+        let result = Picture::new(&[], PixelLayout::Rgb, 0, 0);
+        assert!(matches!(
+            result,
+            Err(WebPEncodingError::VP8_ENC_ERROR_BAD_DIMENSION)
+        ));
+        let result = Picture::new(&rgba[..7], PixelLayout::Rgba, 1, 2);
+        assert!(matches!(
+            result,
+            Err(WebPEncodingError::VP8_ENC_ERROR_BAD_DIMENSION)
+        ));
+        let result = Picture::with_stride(&rgba, PixelLayout::Rgba, 2, 1, 4);
+        assert!(matches!(
+            result,
+            Err(WebPEncodingError::VP8_ENC_ERROR_BAD_DIMENSION)
+        ));
+        let result = Picture::with_stride(&rgba, PixelLayout::Rgba, 1, 2, 9);
+        assert!(matches!(
+            result,
+            Err(WebPEncodingError::VP8_ENC_ERROR_BAD_DIMENSION)
+        ));
+    }
+
+    #[test]
+    fn test_picture_crop_and_view() {
+        let mut picture = numbered();
+        let view = picture.view(1, 0, 2, 2).unwrap();
+        assert!(view.is_view());
+        assert_eq!(gray(&view), [1, 2, 4, 5]);
+        let mut flipped = picture.view(0, 1, 3, 1).unwrap();
+        flipped.flip_horizontal().unwrap();
+        assert_eq!(gray(&flipped), [5, 4, 3]);
+        assert_eq!(
+            gray(&picture),
+            [0, 1, 2, 3, 4, 5],
+            "flipping a view copies it"
+        );
+        let owned = view.into_owned().unwrap();
+        assert!(!owned.is_view());
+        drop(flipped);
+
+        picture.crop(1, 1, 2, 1).unwrap();
+        assert_eq!(gray(&picture), [4, 5]);
+        assert_eq!(gray(&owned), [1, 2, 4, 5]);
+        assert!(picture.crop(1, 0, 2, 1).is_err());
+        assert!(picture.view(0, 0, 0, 1).is_err());
+        assert!(picture.view(u32::MAX, 0, 1, 1).is_err());
+    }
+
+    #[test]
+    fn test_picture_flip() {
+        let mut picture = numbered();
+        picture.flip_vertical().unwrap();
+        assert_eq!(gray(&picture), [3, 4, 5, 0, 1, 2]);
+        picture.flip_horizontal().unwrap();
+        assert_eq!(gray(&picture), [5, 4, 3, 2, 1, 0]);
+    }
+
+    #[test]
+    fn test_picture_alpha() {
+        let mut image = [0u8; 16 * 8 * 4];
+        for (i, pixel) in image.chunks_exact_mut(4).enumerate() {
+            pixel.copy_from_slice(&[i as u8, 1, 2, 0]);
+        }
+        // Make the second 8x8 block partially opaque.
+        image[8 * 4..8 * 4 + 4].copy_from_slice(&[100, 100, 100, 128]);
+        let mut picture = Picture::new(&image, PixelLayout::Rgba, 16, 8).unwrap();
+        assert!(picture.has_transparency());
+        picture.cleanup_transparent_area().unwrap();
+        let cleaned = picture.to_vec();
+        let first = &cleaned[..4];
+        for y in 0..8 {
+            for x in 0..16 {
+                let i = (y * 16 + x) * 4;
+                if x < 8 {
+                    assert_eq!(&cleaned[i..i + 4], first, "the transparent block is flat");
+                } else {
+                    assert_eq!(&cleaned[i..i + 4], &image[i..i + 4]);
+                }
+            }
+        }
+
+        let mut view = picture.view(8, 0, 1, 1).unwrap();
+        view.blend_alpha([0, 0, 255]).unwrap();
+        assert!(!view.has_transparency());
+        let blended = view.to_vec();
+        assert_eq!(blended[3], 255);
+        assert!(blended[0] > 40 && blended[0] < 60 && blended[2] > 170);
+        assert!(picture.has_transparency());
+    }
+
+    #[test]
+    fn test_picture_rescale_and_encode() {
+        let image = [200u8, 100, 50, 255].repeat(16 * 8);
+        let mut picture = Picture::new(&image, PixelLayout::Rgba, 16, 8).unwrap();
+        picture.rescale(4, 0).unwrap();
+        assert_eq!((picture.width(), picture.height()), (4, 2));
+        assert!(picture.rescale(0, 0).is_err());
+
+        let data = Encoder::from_picture(picture).encode_lossless();
+        let decoded = Decoder::new(&data).decode().unwrap();
+        assert_eq!((decoded.width(), decoded.height()), (4, 2));
+        assert_eq!(&*decoded, &[200, 100, 50].repeat(4 * 2)[..]);
+    }
+
+    #[test]
+    fn test_picture_encode_keeps_pixels() {
+        // Transparent pixels with colors, which libwebp replaces when encoding without `exact`.
+        let image = [[10u8, 20, 30, 0], [40, 50, 60, 255]]
+            .concat()
+            .repeat(8 * 8 / 2);
+        let picture = Picture::new(&image, PixelLayout::Rgba, 8, 8).unwrap();
+        let view = picture.view(0, 0, 8, 4).unwrap();
+        let encoder = Encoder::from_picture(view);
+        encoder.encode_lossless();
+        encoder.encode(75.0);
+        assert_eq!(picture.to_vec(), image);
+
+        let mut encoder = Encoder::from_picture(picture.view(0, 0, 8, 4).unwrap());
+        encoder.set_exact(true);
+        let decoded = Decoder::new(&encoder.encode_lossless()).decode().unwrap();
+        assert_eq!(&*decoded, &image[..8 * 4 * 4]);
+        assert_eq!(encoder.to_picture().unwrap().to_vec(), &image[..8 * 4 * 4]);
+    }
+}
@@ -0,0 +1,174 @@
+use libwebp_sys::*;
+
+use crate::{compare, Decoder, DistortionMetric, Encoder, Picture, WebPMemory};
+
+/// Settings for [Encoder::encode_to_target], which searches the lowest lossy quality meeting a target score.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct QualitySearch {
+    /// The metric used to score every candidate.
+    pub metric: DistortionMetric,
+    /// The minimal score over all channels, in decibels like in [DistortionReport](crate::DistortionReport).
+    pub target: f32,
+    /// The maximal number of encodings, including the first one at `max_quality`.
+    pub max_iterations: u32,
+    /// The lowest quality which is tried.
+    pub min_quality: u8,
+    /// The highest quality which is tried.
+    pub max_quality: u8,
+    /// Score PSNR candidates with the statistics libwebp collects while encoding instead of decoding them.
+    /// It's faster, but libwebp measures the PSNR in YUV, so the scores differ slightly.
+    /// Ignored for the other metrics.
+    pub use_encoder_stats: bool,
+}
+
+impl QualitySearch {
+    /// Searches qualities between 0 and 100 with at most 8 encodings, which is enough to find the exact quality.
+    pub fn new(metric: DistortionMetric, target: f32) -> Self {
+        Self {
+            metric,
+            target,
+            max_iterations: 8,
+            min_quality: 0,
+            max_quality: 100,
+            use_encoder_stats: false,
+        }
+    }
+}
+
+/// The result of [Encoder::encode_to_target].
+#[derive(Debug)]
+pub struct QualitySearchResult {
+    /// The encoded image.
+    pub data: WebPMemory,
+    /// The quality `data` was encoded with.
+    pub quality: f32,
+    /// The score of `data`, which is below the target if even `max_quality` doesn't meet it.
+    pub score: f32,
+    /// The number of encodings done.
+    pub iterations: u32,
+}
+
+impl Encoder<'_> {
+    /// Encodes the image lossily with the lowest quality whose score meets the target of the search.
+    ///
+    /// The quality is binary searched between `min_quality` and `max_quality`, assuming that higher qualities score higher.
+    /// If `max_quality` doesn't meet the target, its result is returned.
+    /// If the iterations run out, the lowest quality known to meet the target is returned.
+    /// Every setting of the configuration other than `quality` and `lossless` is kept.
+    pub fn encode_to_target(
+        &self,
+        config: &WebPConfig,
+        search: &QualitySearch,
+    ) -> Result<QualitySearchResult, WebPEncodingError> {
+        if search.min_quality > search.max_quality
+            || search.max_quality > 100
+            || search.max_iterations == 0
+        {
+            return Err(WebPEncodingError::VP8_ENC_ERROR_INVALID_CONFIGURATION);
+        }
+        let reference = self.to_picture()?;
+        let mut config = *config;
+        config.lossless = 0;
+        let mut try_quality = |quality: u8| {
+            config.quality = f32::from(quality);
+            self.encode_and_score(&config, &reference, search)
+                .map(|(data, score)| QualitySearchResult {
+                    data,
+                    quality: config.quality,
+                    score,
+                    iterations: 0,
+                })
+        };
+
+        let mut best = try_quality(search.max_quality)?;
+        // Invariant: `low` doesn't meet the target (or is below the range) and `high` does.
+        let mut low = i32::from(search.min_quality) - 1;
+        let mut high = i32::from(search.max_quality);
+        let mut remaining = search.max_iterations - 1;
+        if best.score >= search.target {
+            while high - low > 1 && remaining > 0 {
+                remaining -= 1;
+                let quality = (low + high) / 2;
+                let candidate = try_quality(quality as u8)?;
+                if candidate.score >= search.target {
+                    high = quality;
+                    best = candidate;
+                } else {
+                    low = quality;
+                }
+            }
+        }
+        best.iterations = search.max_iterations - remaining;
+        Ok(best)
+    }
+
+    fn encode_and_score(
+        &self,
+        config: &WebPConfig,
+        reference: &Picture,
+        search: &QualitySearch,
+    ) -> Result<(WebPMemory, f32), WebPEncodingError> {
+        if search.use_encoder_stats && search.metric == DistortionMetric::Psnr {
+            let mut stats: WebPAuxStats = unsafe { std::mem::zeroed() };
+            let data = self.encode_with_stats(config, Some(&mut stats))?;
+            // The PSNR of Y, U, V, all and alpha.
+            return Ok((data, stats.PSNR[3]));
+        }
+        let data = self.encode_advanced(config)?;
+        let decoded = Decoder::new(&data)
+            .decode()
+            .ok_or(WebPEncodingError::VP8_ENC_ERROR_BAD_WRITE)?;
+        let report = compare(reference, &Picture::try_from(&decoded)?, search.metric)?;
+        Ok((data, report.all))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::shared::noise;
+
+    #[test]
+    fn test_encode_to_target() {
+        let image = noise(48 * 48 * 3);
+        let encoder = Encoder::from_rgb(&image, 48, 48);
+        let config = WebPConfig::new().unwrap();
+        let search = QualitySearch::new(DistortionMetric::Ssim, 9.5);
+        let result = encoder.encode_to_target(&config, &search).unwrap();
+        assert!(result.score >= 9.5);
+        assert!(result.quality < 100.0);
+        assert!(result.iterations <= 8);
+
+        // One quality step lower doesn't meet the target anymore.
+        if result.quality > 0.0 {
+            let lower = encoder.encode(result.quality - 1.0);
+            let decoded = Decoder::new(&lower).decode().unwrap();
+            let report = encoder.compare(&decoded, DistortionMetric::Ssim).unwrap();
+            assert!(report.all < 9.5);
+        }
+
+        let unreachable = QualitySearch {
+            max_quality: 10,
+            ..QualitySearch::new(DistortionMetric::Psnr, 98.0)
+        };
+        let result = encoder.encode_to_target(&config, &unreachable).unwrap();
+        assert_eq!((result.quality, result.iterations), (10.0, 1));
+        assert!(result.score < 98.0);
+
+        let capped = QualitySearch {
+            max_iterations: 2,
+            use_encoder_stats: true,
+            ..QualitySearch::new(DistortionMetric::Psnr, 20.0)
+        };
+        let result = encoder.encode_to_target(&config, &capped).unwrap();
+        assert_eq!(result.iterations, 2);
+        assert!(result.score >= 20.0);
+
+        let invalid = QualitySearch {
+            min_quality: 50,
+            max_quality: 40,
+            ..search
+        };
+        assert!(encoder.encode_to_target(&config, &invalid).is_err());
+    }
+}
@@ -15,6 +15,10 @@ impl Debug for WebPMemory {
     }
 }
 
+// The memory is exclusively owned and `WebPFree` can be called from any thread.
+unsafe impl Send for WebPMemory {}
+unsafe impl Sync for WebPMemory {}
+
 impl Drop for WebPMemory {
     fn drop(&mut self) {
         unsafe { WebPFree(self.0 as _) }
@@ -61,15 +65,18 @@ impl DerefMut for ManageedPicture {
 /// This struct represents a decoded image.
 /// Its data contents can be accessed through the Deref and DerefMut traits.
 /// It is also possible to create an image::DynamicImage from this struct.
+///
+/// The pixels are stored in a Rust-allocated buffer, so [into_vec](WebPImage::into_vec) and
+/// [into_image](WebPImage::into_image) hand them over without copying.
 pub struct WebPImage {
-    data: WebPMemory,
+    data: Vec<u8>,
     layout: PixelLayout,
     width: u32,
     height: u32,
 }
 
 impl WebPImage {
-    pub(crate) fn new(data: WebPMemory, layout: PixelLayout, width: u32, height: u32) -> Self {
+    pub(crate) fn new(data: Vec<u8>, layout: PixelLayout, width: u32, height: u32) -> Self {
         Self {
             data,
             layout,
@@ -94,6 +101,28 @@ impl WebPImage {
         }
     }
 
+    /// Creates a DynamicImage from this WebPImage without copying the pixels.
+    #[cfg(feature = "img")]
+    pub fn into_image(self) -> DynamicImage {
+        let (width, height) = (self.width, self.height);
+        if self.layout.is_alpha() {
+            let image = ImageBuffer::from_raw(width, height, self.data)
+                .expect("ImageBuffer couldn't be created");
+
+            DynamicImage::ImageRgba8(image)
+        } else {
+            let image = ImageBuffer::from_raw(width, height, self.data)
+                .expect("ImageBuffer couldn't be created");
+
+            DynamicImage::ImageRgb8(image)
+        }
+    }
+
+    /// Returns the pixels in the image's pixel layout without copying them.
+    pub fn into_vec(self) -> Vec<u8> {
+        self.data
+    }
+
     /// Returns the width of the image in pixels.
     pub fn width(&self) -> u32 {
         self.width
@@ -148,6 +177,32 @@ impl PixelLayout {
     }
 }
 
+/// Returns the minimum length of a buffer holding `height` rows of `width` pixels, each row starting `stride` bytes
+/// after the previous one. The last row doesn't need padding.
+///
+/// The arithmetic saturates, which is enough to make length checks sound since no slice can be `usize::MAX` bytes long.
+pub(crate) fn min_buffer_len(layout: PixelLayout, width: u32, height: u32, stride: usize) -> usize {
+    if width == 0 || height == 0 {
+        return 0;
+    }
+    let row_len = (width as usize).saturating_mul(layout.bytes_per_pixel().into());
+    stride
+        .saturating_mul(height as usize - 1)
+        .saturating_add(row_len)
+}
+
+/// Returns `len` bytes of a gradient with some deterministic noise, which compresses like a photo in tests.
+#[cfg(test)]
+pub(crate) fn noise(len: usize) -> Vec<u8> {
+    let mut state = 1u32;
+    (0..len)
+        .map(|i| {
+            state = state.wrapping_mul(1103515245).wrapping_add(12345);
+            (i % 192) as u8 + (state >> 28) as u8
+        })
+        .collect()
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -162,13 +217,7 @@ mod tests {
     fn test_webpimage_accessors() {
         let data = vec![10, 20, 30, 40, 50, 60, 70, 80];
 
-        let mut boxed = data.clone().into_boxed_slice();
-        let ptr = boxed.as_mut_ptr();
-        let len = boxed.len();
-        std::mem::forget(boxed);
-
-        let mem = WebPMemory(ptr, len);
-        let img = WebPImage::new(mem, PixelLayout::Rgba, 2, 1);
+        let img = WebPImage::new(data.clone(), PixelLayout::Rgba, 2, 1);
 
         assert_eq!(img.width(), 2);
         assert_eq!(img.height(), 1);
@@ -176,18 +225,13 @@ mod tests {
         assert_eq!(img.layout(), PixelLayout::Rgba);
 
         assert_eq!(&img[..], &data[..]);
+        assert_eq!(img.into_vec(), data);
     }
 
     #[test]
     fn test_webpimage_deref_mut() {
-        let data = vec![1, 2, 3, 4];
-        let mut boxed = data.clone().into_boxed_slice();
-        let ptr = boxed.as_mut_ptr();
-        let len = boxed.len();
-        std::mem::forget(boxed);
-
-        let mem = WebPMemory(ptr, len);
-        let mut img = WebPImage::new(mem, PixelLayout::Rgb, 2, 1);
+        let data = vec![1, 2, 3, 4, 5, 6];
+        let mut img = WebPImage::new(data, PixelLayout::Rgb, 2, 1);
 
         img.deref_mut()[0] = 42;
         assert_eq!(img[0], 42);
@@ -230,7 +274,7 @@ mod tests {
         let pic = unsafe { std::mem::zeroed::<WebPPicture>() };
         let managed = ManageedPicture(pic);
 
-        let inner_ref: &WebPPicture = &*managed;
+        let inner_ref: &WebPPicture = &managed;
         let orig_ptr = &managed.0 as *const WebPPicture;
         let deref_ptr = inner_ref as *const WebPPicture;
         assert_eq!(orig_ptr, deref_ptr);